@@ -25,10 +25,10 @@ pub fn half_adder_bit(a_in: usize, b_in: usize, s_out: usize, c_out: usize) -> U
     let cx_a = CX::new(a_in, s_out);
     let cx_b = CX::new(b_in, s_out);
     let ccx = CCX::new(a_in, b_in, c_out);
-    return U::new(
+    U::new(
         vec![Box::new(cx_a), Box::new(cx_b), Box::new(ccx)],
         String::from("half_addr"),
-    );
+    )
 }
 
 pub fn full_adder_bit(a_in: usize, b_in: usize, c_in: usize, c_out: usize) -> U {
@@ -37,16 +37,16 @@ pub fn full_adder_bit(a_in: usize, b_in: usize, c_in: usize, c_out: usize) -> U
     let cx1 = CX::new(a_in, b_in);
     let ccx2 = CCX::new(b_in, c_in, c_out);
     let cx2 = CX::new(c_in, b_in);
-    return U::new(
+    U::new(
         vec![Box::new(ccx1), Box::new(cx1), Box::new(ccx2), Box::new(cx2)],
         String::from("full_adder_bit"),
-    );
+    )
 }
 
 pub fn full_adder_nbits(a_in: &[usize], b_in: &[usize], c_inout: &[usize]) -> U {
     //! |a⟩|b⟩|0⟩ → |a⟩|a+b⟩|0⟩
     assert_eq!(a_in.len(), b_in.len());
-    assert!(a_in.len() > 0);
+    assert!(!a_in.is_empty());
     check_unique(vec![&a_in, &b_in, &c_inout]);
 
     let mut gate_vec: Vec<Box<dyn Operator>> = Vec::new();
@@ -92,18 +92,16 @@ pub fn full_adder_nbits(a_in: &[usize], b_in: &[usize], c_inout: &[usize]) -> U
     gate_vec.push(Box::new(CCX::new(a, b, c_out)));
     gate_vec.push(Box::new(CX::new(a, b)));
 
-    return U::new(gate_vec, String::from("full_adder"));
+    U::new(gate_vec, String::from("full_adder"))
 }
 
 pub fn substract_nbits(a_in: &[usize], b_in: &[usize], c_inout: &[usize]) -> U {
-    let mut sub = full_adder_nbits(a_in, b_in, c_inout);
-    sub.inverse();
-    return sub;
+    full_adder_nbits(a_in, b_in, c_inout).adjoint()
 }
 
 pub fn add_const_2_power(b: &[usize], m: usize) -> U {
     //! |0⟩|b⟩ → |overflow⟩|b + 2^m⟩
-    assert!(b.len() > 0);
+    assert!(!b.is_empty());
     assert!(b.len() - 1 > m);
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
     for i in (m + 1)..b.len() {
@@ -118,7 +116,7 @@ pub fn add_const_2_power(b: &[usize], m: usize) -> U {
         }
     }
     u_gates.push(Box::new(X::new(b[m])));
-    return U::new(u_gates, String::from("add_const_2^n"));
+    U::new(u_gates, String::from("add_const_2^n"))
 }
 
 /**
@@ -133,10 +131,10 @@ Add the input 2 to the power of m to b and store the result in b. Store overflow
 Required number of qubits: n(b) + 1(overflow) = **n + 1**
 */
 pub fn overflow_qadd_const_2_power(b: &[usize], overflow: usize, m: usize) -> U {
-    assert!(b.len() > 0);
+    assert!(!b.is_empty());
     assert!(b.len() > m);
-    check_unique(vec![&b, &vec![overflow]]);
-    let b = &[b, &vec![overflow]].concat();
+    check_unique(vec![&b, &[overflow]]);
+    let b = &[b, &[overflow]].concat();
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
     for i in (m + 1)..b.len() {
         let i = b.len() - i + m;
@@ -150,7 +148,7 @@ pub fn overflow_qadd_const_2_power(b: &[usize], overflow: usize, m: usize) -> U
         }
     }
     u_gates.push(Box::new(X::new(b[m])));
-    return U::new(u_gates, String::from("o_qadd_const_2^n"));
+    U::new(u_gates, String::from("o_qadd_const_2^n"))
 }
 
 // wrapping_qadd_const_2_power
@@ -164,7 +162,7 @@ Wrapping add the input 2 to the power of m to b and store the result in b.
 Required number of qubits: n(b) = **n**
 */
 pub fn wrapping_qadd_const_2_power(b: &[usize], m: usize) -> U {
-    assert!(b.len() > 0);
+    assert!(!b.is_empty());
     assert!(b.len() > m);
     check_unique(vec![&b]);
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
@@ -180,7 +178,7 @@ pub fn wrapping_qadd_const_2_power(b: &[usize], m: usize) -> U {
         }
     }
     u_gates.push(Box::new(X::new(b[m])));
-    return U::new(u_gates, String::from("w_qadd_const_2^n"));
+    U::new(u_gates, String::from("w_qadd_const_2^n"))
 }
 
 /**
@@ -205,7 +203,7 @@ pub fn add_const(b: &[usize], a_const: usize) -> U {
         }
     }
 
-    return U::new(u_gates, String::from("add_const"));
+    U::new(u_gates, String::from("add_const"))
 }
 
 // overflow_qadd_const
@@ -221,10 +219,10 @@ Add const_a to b and store the result in b. Store overflow results
 Required number of qubits: n(b) + 1(overflow) = **n + 1**
  */
 pub fn overflow_qadd_const(b: &[usize], overflow: usize, a_const: usize) -> U {
-    assert!(b.len() > 0);
+    assert!(!b.is_empty());
     assert!((a_const >> (b.len())) == 0);
 
-    check_unique(vec![&b, &vec![overflow]]);
+    check_unique(vec![&b, &[overflow]]);
 
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
     for i in 0..(b.len()) {
@@ -233,7 +231,7 @@ pub fn overflow_qadd_const(b: &[usize], overflow: usize, a_const: usize) -> U {
         }
     }
 
-    return U::new(u_gates, String::from("o_qadd_const"));
+    U::new(u_gates, String::from("o_qadd_const"))
 }
 
 // wrapping_qadd_const
@@ -248,7 +246,7 @@ Wrapping add const_a to b and store the result in b.
 Required number of qubits: n(b) = **n**
  */
 pub fn wrapping_qadd_const(b: &[usize], a_const: usize) -> U {
-    assert!(b.len() > 0);
+    assert!(!b.is_empty());
     assert!((a_const >> (b.len())) == 0);
 
     check_unique(vec![&b]);
@@ -260,14 +258,14 @@ pub fn wrapping_qadd_const(b: &[usize], a_const: usize) -> U {
         }
     }
 
-    return U::new(u_gates, String::from("w_qadd_const"));
+    U::new(u_gates, String::from("w_qadd_const"))
 }
 
 pub fn sub_const(b: &[usize], a_const: usize) -> U {
     //! |0⟩|b⟩|0⟩ → |sign⟩|b + a⟩|0⟩
     let mut sub = add_const(b, a_const);
     sub.inverse();
-    return U::new(sub.gates, String::from("sub_const"));
+    U::new(sub.gates, String::from("sub_const"))
 }
 
 /**
@@ -285,7 +283,7 @@ pub fn overflow_qsub_const(b: &[usize], overflow: usize, a_const: usize) -> U {
     let mut sub = overflow_qadd_const(b, overflow, a_const);
     sub.inverse();
     sub.rename(String::from("o_qsub_const"));
-    return sub;
+    sub
 }
 
 /**
@@ -303,7 +301,7 @@ pub fn wrapping_qsub_const(b: &[usize], a_const: usize) -> U {
     let mut sub = wrapping_qadd_const(b, a_const);
     sub.inverse();
     sub.rename(String::from("w_qsub_const"));
-    return sub;
+    sub
 }
 
 /**
@@ -317,14 +315,10 @@ pub fn swap(a_in: &[usize], b_in: &[usize]) -> U {
 
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
     for i in 0..a_in.len() {
-        let a = a_in[i];
-        let b = b_in[i];
-        u_gates.push(Box::new(CX::new(a, b)));
-        u_gates.push(Box::new(CX::new(b, a)));
-        u_gates.push(Box::new(CX::new(a, b)));
+        u_gates.push(Box::new(SWAP::new(a_in[i], b_in[i])));
     }
 
-    return U::new(u_gates, String::from("swap"));
+    U::new(u_gates, String::from("swap"))
 }
 
 /**
@@ -344,7 +338,7 @@ pub fn mod_add(
     assert_eq!(n_in.len(), b.len());
     assert_eq!(zero.len(), b.len());
     assert!(num >> (n_in.len() - 1) == 0);
-    check_unique(vec![&a, &b, &n_in, &zero, &vec![t]]);
+    check_unique(vec![&a, &b, &n_in, &zero, &[t]]);
 
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
 
@@ -355,14 +349,12 @@ pub fn mod_add(
 
     // (3)[flag] |0⟩ ->  |0⟩ (if a + b < N), |1⟩ (if a + b >= N)
     let b_max = &b[b.len() - 1];
-    u_gates.push(Box::new(X::new(*b_max)));
-    u_gates.push(Box::new(CX::new(*b_max, t)));
-    u_gates.push(Box::new(X::new(*b_max)));
+    u_gates.push(Box::new(CX::new_polarized(*b_max, false, t)));
 
     // (4)[arrow] |N⟩ -> |0⟩ (if a + b < N), |N⟩ (if a + b >= N)
-    for idx in 0..n_in.len() {
+    for (idx, n) in n_in.iter().enumerate() {
         if (num >> idx) & 1 == 1 {
-            u_gates.push(Box::new(CX::new(t, n_in[idx])));
+            u_gates.push(Box::new(CX::new(t, *n)));
         }
     }
 
@@ -370,9 +362,9 @@ pub fn mod_add(
     u_gates.extend(full_adder_nbits(n_in, b, zero).gates);
 
     // (6)[arrow] |0 or N⟩ -> |N⟩
-    for idx in 0..n_in.len() {
+    for (idx, n) in n_in.iter().enumerate() {
         if (num >> idx) & 1 == 1 {
-            u_gates.push(Box::new(CX::new(t, n_in[idx])));
+            u_gates.push(Box::new(CX::new(t, *n)));
         }
     }
 
@@ -385,7 +377,7 @@ pub fn mod_add(
     // (9)[add] |a⟩|b or b-N⟩ -> |a⟩|a+b or a+b-N⟩
     u_gates.extend(full_adder_nbits(a, b, zero).gates);
 
-    return U::new(u_gates, String::from("moduler_adder"));
+    U::new(u_gates, String::from("moduler_adder"))
 }
 
 /**
@@ -402,8 +394,8 @@ A quantum circuit that adds constants and multiplies modular operations using co
 Required number of qubits: n(b) + 1(overflow) = **n + 1**
 */
 pub fn mod_add_const(b: &[usize], overflow: usize, a_const: usize, n_const: usize) -> U {
-    assert!(b.len() > 0);
-    check_unique(vec![&b, &vec![overflow]]);
+    assert!(!b.is_empty());
+    check_unique(vec![&b, &[overflow]]);
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
 
     // (1)[add_a] |b⟩ -> |a+b⟩
@@ -430,7 +422,7 @@ pub fn mod_add_const(b: &[usize], overflow: usize, a_const: usize, n_const: usiz
     // (7)[add_a] |b-N or b⟩ -> |a+b-N or a+b⟩
     u_gates.extend(wrapping_qadd_const(b, a_const).gates);
 
-    return U::new(u_gates, String::from("mod_add_const"));
+    U::new(u_gates, String::from("mod_add_const"))
 }
 
 /**
@@ -455,28 +447,26 @@ pub fn cmm_const(
     assert!(tar_reg.len() == x.len());
     assert!(a_const < (1 << x.len()));
     assert!(n_const < (1 << x.len()));
-    check_unique(vec![x, tar_reg, &vec![cont, overflow]]);
+    check_unique(vec![x, tar_reg, &[cont, overflow]]);
 
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
 
-    let mut mul: Vec<Box<dyn Operator>> = Vec::new();
-
-    for i in 0..x.len() {
+    for (i, x_i) in x.iter().enumerate() {
         let adder = mod_add_const(tar_reg, overflow, (a_const << i) % n_const, n_const);
-        mul.push(Box::new(CU::from_u(x[i], adder)));
+        u_gates.push(Box::new(CU::new_multi(
+            vec![cont, *x_i],
+            adder.gates,
+            String::from("ccu-madd"),
+        )));
     }
 
-    u_gates.push(Box::new(CU::new(cont, mul, String::from("cu-mmul"))));
-
-    u_gates.push(Box::new(X::new(cont)));
-
     for i in 0..x.len() {
-        u_gates.push(Box::new(CCX::new(cont, x[i], tar_reg[i])));
+        u_gates.push(Box::new(CCX::new_polarized(
+            cont, false, x[i], true, tar_reg[i],
+        )));
     }
 
-    u_gates.push(Box::new(X::new(cont)));
-
-    return U::new(u_gates, String::from("cmm_const"));
+    U::new(u_gates, String::from("cmm_const"))
 }
 
 /**
@@ -499,29 +489,30 @@ pub fn me_const(
     n_const: usize,
 ) -> U {
     assert!(zero.len() == a_x.len());
-    assert!(a_x.len() >= 1);
+    assert!(!a_x.is_empty());
     assert!(is_coprime(a_const, n_const));
-    check_unique(vec![&x, &a_x, &vec![overflow]]);
+    check_unique(vec![&x, &a_x, &[overflow]]);
     let mut u_gates: Vec<Box<dyn Operator>> = Vec::new();
 
     // a^x |0⟩ -> |1⟩
     u_gates.push(Box::new(X::new(a_x[0])));
 
-    for i in 0..x.len() {
-        let x_i = x[i];
+    let sw = swap(a_x, zero);
+    for (i, x_i) in x.iter().copied().enumerate() {
         let const_a_xi = mod_power(a_const, 1 << i, n_const);
         let _const_a_xi = mod_inv(const_a_xi, n_const);
         //[cmm] |x⟩|0⟩ -> |x⟩|0 + x * a^2^x_n mod N⟩
         u_gates.extend(cmm_const(a_x, zero, overflow, x_i, const_a_xi, n_const).gates);
-        u_gates.extend(swap(a_x, zero).gates);
+        u_gates.extend(sw.gates.clone());
         //[icmm] |x⟩|x * a^2^x_n mod N⟩ -> |x - x * a^2^x_n * a^(-2^x_n)⟩|x * a^2^x_n mod N⟩
         //                              -> |0⟩|x * a^2^x_n mod N⟩
-        let mut icmm = cmm_const(a_x, zero, overflow, x_i, _const_a_xi, n_const);
-        icmm.inverse();
-        u_gates.extend(icmm.gates);
+        // the constant is a^(-2^x_n), not a^2^x_n, so this is not the forward cmm reversed and has
+        // to be built on its own; only its adjoint is taken
+        let icmm = cmm_const(a_x, zero, overflow, x_i, _const_a_xi, n_const);
+        u_gates.extend(icmm.adjoint().gates);
     }
 
-    return U::new(u_gates, String::from("me_const"));
+    U::new(u_gates, String::from("me_const"))
 }
 
 /**
@@ -546,16 +537,11 @@ pub fn qft(x: &[usize]) -> U {
         u_gates.push(Box::new(H::new(x[i])));
         for j in (i + 1)..n {
             let angle = (-((j + 1 - i) as f64)).exp2();
-            let r = R::new(x[i], 2.0 * PI * angle);
-            u_gates.push(Box::new(CU::new(
-                x[j],
-                vec![Box::new(r)],
-                format!("r_+2^-{}", j + 1 - i),
-            )));
+            u_gates.push(Box::new(CPhase::new(x[j], x[i], 2.0 * PI * angle)));
         }
     }
 
-    return U::new(u_gates, String::from("qft"));
+    U::new(u_gates, String::from("qft"))
 }
 
 /**
@@ -564,10 +550,7 @@ Circuit that performs inverse quantum Fourier transform
 Σexp(i2πkj / 2^n)|k⟩ → |j⟩
 */
 pub fn inv_qft(x: &[usize]) -> U {
-    let mut u = qft(x);
-    u.inverse();
-
-    return u;
+    qft(x).adjoint()
 }
 
 fn check_unique(vecs: Vec<&[usize]>) {
@@ -578,5 +561,4 @@ fn check_unique(vecs: Vec<&[usize]>) {
             set.insert(*idx);
         }
     }
-    return;
 }
@@ -0,0 +1,123 @@
+/*!
+ Bit-level simulation of reversible circuits.
+
+ Circuits made only of X, CX, CCX and CNX (such as the adders in the circuits module) map every
+ basis state to another basis state. Such circuits can be evaluated on a single basis input by
+ flipping bits of an integer, which costs O(gates) instead of O(2^n) and therefore works for far
+ more qubits than a state vector can hold.
+
+ Diagonal gates such as Z and R only change the phase of a basis state, so they are accepted and
+ ignored. Any gate that creates a superposition (e.g. H) is reported as an error.
+
+# Example
+```
+use Qit::circuits::mod_add_const;
+use Qit::classical::run_classical;
+
+// 40-bit modular addition: 41 qubits, impossible as a state vector.
+let b: Vec<usize> = (0..40).collect();
+let n_const = 1_000_000_000_039;
+let u = mod_add_const(&b, 40, 123_456_789_012, n_const);
+let out = run_classical(&u, 41, 999_999_999_999).unwrap();
+assert_eq!(out, (123_456_789_012 + 999_999_999_999) % n_const as u128);
+```
+*/
+
+use super::core::{Applicable, Comp, ElementarySink, Matrix2, QuantumState, UnsupportedGate};
+
+const EPS: f64 = 1e-12;
+
+/**
+A single computational basis state |value⟩ of up to 128 qubits.
+
+Bit i of `value` is the state of qubit i, as in [`Qubits::from_num`](crate::core::Qubits::from_num).
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BasisState {
+    pub size: usize,
+    pub value: u128,
+}
+
+impl BasisState {
+    pub fn new(size: usize, value: u128) -> Self {
+        assert!(size <= 128);
+        assert!(size == 128 || (value >> size) == 0);
+        BasisState { size, value }
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        (self.value >> index) & 1 == 1
+    }
+}
+
+impl ElementarySink for BasisState {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        assert!(target < self.size);
+        let is_zero = |c: Comp| c.abs_square() < EPS;
+        let flip = if is_zero(matrix[0][1]) && is_zero(matrix[1][0]) {
+            false
+        } else if is_zero(matrix[0][0]) && is_zero(matrix[1][1]) {
+            true
+        } else {
+            return Err(String::from("gate is not a permutation of basis states"));
+        };
+
+        for c in controls {
+            assert!(*c < self.size);
+            if !self.bit(*c) {
+                return Ok(());
+            }
+        }
+        if flip {
+            self.value ^= 1 << target;
+        }
+        Ok(())
+    }
+}
+
+impl QuantumState for BasisState {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let mut state = *self;
+        op.lower(&mut state, &[])?;
+        *self = state;
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        self.bit(target)
+    }
+
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        let mut probs = vec![0.0; 1 << tar.len()];
+        let mut tar_idx = 0;
+        for (j, t) in tar.iter().enumerate() {
+            if self.bit(*t) {
+                tar_idx |= 1 << j;
+            }
+        }
+        probs[tar_idx] = 1.0;
+        probs
+    }
+}
+
+/**
+Evaluate a reversible circuit on the basis input |input⟩ of `size` qubits and return the output basis state.
+*/
+pub fn run_classical(
+    op: &dyn Applicable,
+    size: usize,
+    input: u128,
+) -> Result<u128, UnsupportedGate> {
+    let mut state = BasisState::new(size, input);
+    state.apply(op)?;
+    Ok(state.value)
+}
@@ -24,6 +24,7 @@ q_out.print_cmps();
 ```
 */
 
+use std::any::Any;
 use std::fmt;
 use std::ops;
 
@@ -73,15 +74,15 @@ pub mod mod_funcs;
 
 impl Comp {
     pub fn new(re: f64, im: f64) -> Self {
-        return Comp(re, im);
+        Comp(re, im)
     }
 
     pub fn abs_square(&self) -> f64 {
-        return self.0 * self.0 + self.1 * self.1;
+        self.0 * self.0 + self.1 * self.1
     }
 
     pub fn zero() -> Self {
-        return Comp(0.0, 0.0);
+        Comp(0.0, 0.0)
     }
 }
 
@@ -99,45 +100,45 @@ impl fmt::Display for Comp {
 impl ops::Add<Comp> for Comp {
     type Output = Comp;
     fn add(self, _rhs: Comp) -> Comp {
-        return Comp(self.0 + _rhs.0, self.1 + _rhs.1);
+        Comp(self.0 + _rhs.0, self.1 + _rhs.1)
     }
 }
 
 impl ops::Add<f64> for Comp {
     type Output = Comp;
     fn add(self, rhs: f64) -> Comp {
-        return Comp(self.0 + rhs, self.1);
+        Comp(self.0 + rhs, self.1)
     }
 }
 
 impl ops::Sub<Comp> for Comp {
     type Output = Comp;
     fn sub(self, _rhs: Comp) -> Comp {
-        return Comp(self.0 - _rhs.0, self.1 - _rhs.1);
+        Comp(self.0 - _rhs.0, self.1 - _rhs.1)
     }
 }
 
 impl ops::Sub<f64> for Comp {
     type Output = Comp;
     fn sub(self, rhs: f64) -> Comp {
-        return Comp(self.0 - rhs, self.1);
+        Comp(self.0 - rhs, self.1)
     }
 }
 
 impl ops::Mul<Comp> for Comp {
     type Output = Comp;
     fn mul(self, _rhs: Comp) -> Comp {
-        return Comp(
+        Comp(
             self.0 * _rhs.0 - self.1 * _rhs.1,
             self.0 * _rhs.1 + self.1 * _rhs.0,
-        );
+        )
     }
 }
 
 impl ops::Mul<f64> for Comp {
     type Output = Comp;
     fn mul(self, rhs: f64) -> Comp {
-        return Comp(self.0 * rhs, self.1 * rhs);
+        Comp(self.0 * rhs, self.1 * rhs)
     }
 }
 
@@ -186,10 +187,7 @@ impl Qubits {
         assert!((1 << size) > number);
         let mut bits = vec![Comp::zero(); 1 << size];
         bits[number] = Comp(1.0, 0.0);
-        return Qubits {
-            size: size,
-            bits: bits,
-        };
+        Qubits { size, bits }
     }
 
     pub fn from_comp(size: usize, number: usize, comp: Comp) -> Self {
@@ -197,18 +195,12 @@ impl Qubits {
         assert!(comp.abs_square() == 1.0);
         let mut bits = vec![Comp::zero(); 1 << size];
         bits[number] = comp;
-        return Qubits {
-            size: size,
-            bits: bits,
-        };
+        Qubits { size, bits }
     }
 
     pub fn from_bits(size: usize, bits: Vec<Comp>) -> Self {
         assert_eq!(1 << size, bits.len());
-        return Qubits {
-            size: size,
-            bits: bits,
-        };
+        Qubits { size, bits }
     }
 
     /**
@@ -217,10 +209,7 @@ impl Qubits {
     pub fn zeros(size: usize) -> Self {
         let mut bits = vec![Comp::zero(); 1 << size];
         bits[0] = Comp(1.0, 0.0);
-        return Qubits {
-            size: size,
-            bits: bits,
-        };
+        Qubits { size, bits }
     }
 
     /**
@@ -252,11 +241,7 @@ impl Qubits {
     }
 
     pub fn probs(&self) -> Vec<f64> {
-        let mut prob = vec![0.0; (1 << self.size)];
-        for index in 0..(1 << self.size) {
-            prob[index] = self.bits[index].abs_square();
-        }
-        return prob;
+        self.bits.iter().map(|b| b.abs_square()).collect()
     }
 
     /**
@@ -272,47 +257,41 @@ impl Qubits {
                 max_idx = i;
             }
         }
-        return max_idx;
+        max_idx
     }
 
     /**
     Function to obtain probability distribution of qubits
      */
     pub fn _measure(&self, tar: &[usize]) -> Vec<f64> {
-        let mut probs: Vec<f64> = Vec::new();
-        for _ in 0..(1 << tar.len()) {
-            probs.push(0.0);
-        }
+        let mut probs = vec![0.0; 1 << tar.len()];
         for i in 0..(1 << self.size) {
             let mut tar_idx = 0;
-            for j in 0..tar.len() {
-                tar_idx |= (1 & (i >> tar[j])) << j;
+            for (j, t) in tar.iter().enumerate() {
+                tar_idx |= (1 & (i >> t)) << j;
             }
             probs[tar_idx] += self.bits[i].abs_square();
         }
 
-        return probs;
+        probs
     }
 
     pub fn _print_measure(&self, tar: &[usize]) {
-        let mut probs: Vec<f64> = Vec::new();
-        for _ in 0..(1 << tar.len()) {
-            probs.push(0.0);
-        }
+        let mut probs = vec![0.0; 1 << tar.len()];
         for i in 0..(1 << self.size) {
             let mut tar_idx = 0;
-            for j in 0..tar.len() {
-                tar_idx |= (1 & (i >> tar[j])) << j;
+            for (j, t) in tar.iter().enumerate() {
+                tar_idx |= (1 & (i >> t)) << j;
             }
             probs[tar_idx] += self.bits[i].abs_square();
         }
 
-        for index in 0..(1 << tar.len()) {
+        for (index, prob) in probs.iter().enumerate() {
             println!(
                 "|{index:0>size$b}⟩ : {prob:>3.2}%",
                 index = index,
                 size = tar.len(),
-                prob = (probs[index] * 10000.0).round() / 100.0
+                prob = (prob * 10000.0).round() / 100.0
             );
         }
     }
@@ -324,40 +303,366 @@ impl Qubits {
 pub trait Applicable {
     fn apply(&self, qubits: Qubits) -> Qubits {
         let it = BitSlideIndex::new(1 << qubits.size, 0);
-        return self.apply_iter(qubits, &it);
+        self.apply_iter(qubits, &it)
     }
     fn name(&self) -> String;
+
+    /**
+    Structured description of the gate, for walking a circuit without parsing `name`.
+
+    Defaults to [`GateKind::Opaque`] with `name` as label and no qubits, so gates defined outside
+    this crate keep working; such gates cannot be analysed further.
+     */
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::Opaque, Vec::new(), Vec::new(), Vec::new());
+        info.label = self.name();
+        info
+    }
     fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits;
+
+    /**
+    Feed the gate to `sink` as a sequence of controlled single-qubit matrices.
+
+    `controls` are the control bits added by enclosing gates such as CU.
+    Gates that cannot be expressed this way keep the default, which reports them as unsupported.
+     */
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let _ = (sink, controls);
+        Err(UnsupportedGate::new(
+            self.name(),
+            "gate cannot be lowered to controlled single-qubit matrices",
+        ))
+    }
+}
+
+/**
+Kind of a gate reported by [`Applicable::describe`].
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GateKind {
+    H,
+    X,
+    Y,
+    Z,
+    R,
+    S,
+    Sdg,
+    T,
+    Tdg,
+    SX,
+    SXdg,
+    RX,
+    RY,
+    RZ,
+    U3,
+    CX,
+    CZ,
+    CPhase,
+    CCX,
+    CNX,
+    MCZ,
+    SWAP,
+    ISWAP,
+    ISWAPdg,
+    CSWAP,
+    Matrix,
+    PauliRotation,
+    PhaseOracle,
+    PermutationOracle,
+    Pow,
+    CU,
+    U,
+    /**
+    A gate that does not override [`Applicable::describe`], or a gate defined outside this crate
+    that only reports its qubits.
+     */
+    Opaque,
+}
+
+/**
+Structured description of a gate.
+
+* kind: what the gate does.
+* targets: qubits the gate acts on, in the order of the gate's constructor.
+* controls: control qubits, with `polarities[i]` false when `controls[i]` fires on |0⟩.
+* params: angles, e.g. `[angle]` for R and RX or `[theta, phi, lambda]` for U3.
+* label: user given label of U, CU, MatrixGate and the oracles, the Pauli string of PauliRotation,
+  or the name of an Opaque gate. Empty for the other gates.
+* matrix: the matrix of MatrixGate.
+* children: the gates of U and CU, or the operator of Pow (whose params are `[k]`).
+
+# Example
+```
+use Qit::core::{Applicable, GateKind};
+use Qit::gates::{CCX, CU, H};
+
+let cu = CU::new(3, vec![Box::new(H::new(0)), Box::new(CCX::new(0, 1, 2))], String::from("cu"));
+let info = cu.describe();
+assert_eq!(info.kind, GateKind::CU);
+assert_eq!(info.controls, vec![3]);
+assert_eq!(info.children[1].kind, GateKind::CCX);
+assert_eq!(info.children[1].controls, vec![0, 1]);
+assert_eq!(info.children[1].targets, vec![2]);
+assert_eq!(info.qubits(), vec![0, 1, 2, 3]);
+```
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateInfo {
+    pub kind: GateKind,
+    pub targets: Vec<usize>,
+    pub controls: Vec<usize>,
+    pub polarities: Vec<bool>,
+    pub params: Vec<f64>,
+    pub label: String,
+    pub matrix: Option<Vec<Vec<Comp>>>,
+    pub children: Vec<GateInfo>,
+}
+
+impl GateInfo {
+    /**
+    Description with positive controls, no label, no matrix and no children.
+     */
+    pub fn new(
+        kind: GateKind,
+        targets: Vec<usize>,
+        controls: Vec<usize>,
+        params: Vec<f64>,
+    ) -> Self {
+        let polarities = vec![true; controls.len()];
+        GateInfo {
+            kind,
+            targets,
+            controls,
+            polarities,
+            params,
+            label: String::new(),
+            matrix: None,
+            children: Vec::new(),
+        }
+    }
+
+    /**
+    All qubits touched by the gate and its children, sorted and without duplicates.
+     */
+    pub fn qubits(&self) -> Vec<usize> {
+        let mut qubits = self.targets.clone();
+        qubits.extend_from_slice(&self.controls);
+        for child in self.children.iter() {
+            qubits.extend(child.qubits());
+        }
+        qubits.sort();
+        qubits.dedup();
+        qubits
+    }
+}
+
+/**
+2x2 complex matrix acting on a single qubit, indexed as `matrix[row][column]`.
+ */
+pub type Matrix2 = [[Comp; 2]; 2];
+
+/**
+Receiver of gates lowered by [`Applicable::lower`].
+
+Simulators that do not store a full state vector implement this trait to run the structs of the gates module.
+An `Err` carries the reason why the matrix cannot be handled.
+ */
+pub trait ElementarySink {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String>;
 }
 
+/**
+Common interface of the simulation backends.
+
+The same circuit built from the gates and circuits modules can run unchanged on any implementation:
+[`Qubits`] (the default state vector), [`SparseQubits`](crate::sparse::SparseQubits),
+[`StabilizerState`](crate::stabilizer::StabilizerState),
+[`MatrixProductState`](crate::mps::MatrixProductState), [`DensityMatrix`](crate::density::DensityMatrix)
+and [`BasisState`](crate::classical::BasisState).
+
+# Example
+```
+use Qit::core::{QuantumState, Qubits};
+use Qit::density::DensityMatrix;
+use Qit::gates::{CX, H, OperatorVec, PushOps, U};
+use Qit::sparse::SparseQubits;
+use Qit::stabilizer::StabilizerState;
+
+fn bell_probs(state: &mut dyn QuantumState) -> Vec<f64> {
+    let mut circ = OperatorVec::new();
+    circ.push_ops(H::new(0));
+    circ.push_ops(CX::new(0, 1));
+    state.apply(&U::new(circ, String::from("bell"))).unwrap();
+    return state.marginal_probs(&[0, 1]);
+}
+
+for probs in [
+    bell_probs(&mut Qubits::zeros(2)),
+    bell_probs(&mut SparseQubits::zeros(2)),
+    bell_probs(&mut StabilizerState::new(2)),
+    bell_probs(&mut DensityMatrix::zeros(2)),
+] {
+    assert!((probs[0b00] - 0.5).abs() < 1e-9);
+    assert!((probs[0b11] - 0.5).abs() < 1e-9);
+}
+```
+ */
+pub trait QuantumState: ElementarySink {
+    fn num_qubits(&self) -> usize;
+
+    /**
+    Apply a gate (or circuit) to the state. On error the state is left unchanged, so a circuit is
+    never half applied.
+     */
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate>;
+
+    /**
+    Measure `target` in the computational basis, collapsing the state.
+     */
+    fn measure(&mut self, target: usize) -> bool;
+
+    /**
+    Probability distribution of the qubits in `tar`. Index bit j corresponds to `tar[j]`.
+     */
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64>;
+}
+
+impl ElementarySink for Qubits {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        let step = 1 << target;
+        let mut mask = 0;
+        for c in controls {
+            mask |= 1 << c;
+        }
+        let iter = BitSlideIndex::new(1 << self.size, mask).merge(step);
+        for idx1 in iter {
+            let idx0 = idx1 - step;
+            let (a0, a1) = (self.bits[idx0], self.bits[idx1]);
+            self.bits[idx0] = matrix[0][0] * a0 + matrix[0][1] * a1;
+            self.bits[idx1] = matrix[1][0] * a0 + matrix[1][1] * a1;
+        }
+        Ok(())
+    }
+}
+
+impl QuantumState for Qubits {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let qubits = Qubits {
+            size: self.size,
+            bits: std::mem::take(&mut self.bits),
+        };
+        *self = op.apply(qubits);
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        use rand::prelude::*;
+        let p1 = self._measure(&[target])[1];
+        let outcome = rand::thread_rng().gen::<f64>() < p1;
+        let norm = 1.0 / (if outcome { p1 } else { 1.0 - p1 }).sqrt();
+        for index in 0..(1 << self.size) {
+            if ((index >> target) & 1 == 1) == outcome {
+                self.bits[index] = self.bits[index] * norm;
+            } else {
+                self.bits[index] = Comp::zero();
+            }
+        }
+        outcome
+    }
+
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        self._measure(tar)
+    }
+}
+
+/**
+Error returned when a simulator meets a gate it cannot handle.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsupportedGate {
+    pub gate: String,
+    pub reason: String,
+}
+
+impl UnsupportedGate {
+    pub fn new(gate: String, reason: &str) -> Self {
+        UnsupportedGate {
+            gate,
+            reason: String::from(reason),
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedGate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unsupported gate {}: {}", self.gate, self.reason)
+    }
+}
+
+impl std::error::Error for UnsupportedGate {}
+
 /**
 struct used internally when applying gates
+
+Enumerates, in ascending order, the indices below `to` whose bits selected by `mask` are equal to `value`.
  */
 pub struct BitSlideIndex {
     idx: usize,
     pub mask: usize,
+    pub value: usize,
     to: usize,
 }
 
 impl BitSlideIndex {
     pub fn new(to: usize, mask: usize) -> Self {
-        return BitSlideIndex {
+        BitSlideIndex::new_with(to, mask, mask)
+    }
+
+    /**
+    Enumerate the indices whose masked bits equal `value`. Bits of `mask` that are 0 in `value`
+    select indices where that qubit is |0⟩ (e.g. negative controls).
+     */
+    pub fn new_with(to: usize, mask: usize, value: usize) -> Self {
+        assert!(value & !mask == 0, "value has bits outside of mask.");
+        BitSlideIndex {
             idx: 0,
-            mask: mask,
-            to: to,
-        };
+            mask,
+            value,
+            to,
+        }
     }
 
     pub fn merge(&self, other: usize) -> Self {
+        self.merge_with(other, other)
+    }
+
+    /**
+    Add the bits of `other` to the mask, requiring them to be equal to `value`.
+     */
+    pub fn merge_with(&self, other: usize, value: usize) -> Self {
         if self.mask & other > 0 {
             println!("self.mask:{:b}, other_mask:{:b}", self.mask, other);
             panic!("invalid mask was input.");
         }
-        return BitSlideIndex {
-            idx: 0,
-            mask: self.mask | other,
-            to: self.to,
-        };
+        BitSlideIndex::new_with(self.to, self.mask | other, self.value | value)
     }
 
     pub fn init(&mut self) {
@@ -369,34 +674,101 @@ impl Iterator for BitSlideIndex {
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let idx = self.idx;
-        if (idx & self.mask) != self.mask {
-            let idx = idx | self.mask;
-            self.idx = idx + 1;
-            if idx < self.to {
-                return Some(idx);
-            }
-        } else {
-            self.idx = idx + 1;
-            if idx < self.to {
-                return Some(idx);
-            }
+        // self.idx only holds the free (unmasked) bits
+        let idx = self.idx | self.value;
+        if idx >= self.to {
+            return None;
         }
-        return None;
+        self.idx = ((self.idx | self.mask) + 1) & !self.mask;
+        Some(idx)
     }
 }
 
 /**
 Trait that implements make gates inversed
+
+`dagger` is required, so that a new gate cannot silently fall back to a wrong adjoint.
+Self-inverse gates return a clone of themselves.
  */
-pub trait Inversible {
-    fn inverse(&mut self) {}
+pub trait Inversible: OperatorClone + Any {
+    /**
+    Return the adjoint as a new operator, leaving `self` unchanged.
+     */
+    fn dagger(&self) -> Box<dyn Operator>;
+
+    /**
+    Turn the gate into its adjoint in place by replacing it with `dagger`.
+
+    Panics if `dagger` returns an operator of another type. Every gate of this crate returns its own
+    type; on a `Box<dyn Operator>` use `dagger` instead.
+     */
+    fn inverse(&mut self)
+    where
+        Self: Sized,
+    {
+        let adjoint: Box<dyn Any> = self.dagger();
+        *self = *adjoint
+            .downcast::<Self>()
+            .expect("dagger returned an operator of another type");
+    }
 }
 
 /**
-A trait that combines the Applicable and Inversible traits.
+Trait that clones an operator through `Box<dyn Operator>`.
+
+It is implemented for every `Clone` operator, so gates only need `#[derive(Clone)]`.
  */
-pub trait Operator: Applicable + Inversible {}
+pub trait OperatorClone {
+    fn box_clone(&self) -> Box<dyn Operator>;
+}
+
+impl<T: 'static + Operator + Clone> OperatorClone for T {
+    fn box_clone(&self) -> Box<dyn Operator> {
+        Box::new(self.clone())
+    }
+}
+
+/**
+A trait that combines the Applicable, Inversible and OperatorClone traits.
+ */
+pub trait Operator: Applicable + Inversible + OperatorClone {
+    /**
+    The k-th power as a single gate, when that is cheaper than applying the gate k times
+    (e.g. R(θ)^k = R(kθ)). Used by [`Pow`](crate::gates::Pow); `None` makes Pow repeat the gate.
+     */
+    fn power(&self, _k: usize) -> Option<Box<dyn Operator>> {
+        None
+    }
+
+    /**
+    The same gate with every qubit index q replaced by `mapping(q)`, through the gates of U and CU.
+    Panics if the mapping sends two qubits of one gate to the same index.
+
+    `None` when the gate cannot be relabelled. That is the default, so a gate defined outside this
+    crate supports remapping only by overriding this method; a U or CU containing it gives `None`.
+    Every gate of this crate returns `Some`.
+
+    # Usage
+    ```
+    use Qit::circuits::wrapping_qadd_const;
+    use Qit::core::{Applicable, Operator, Qubits};
+
+    // adder built for [0, 1, 2], reused on [3, 4, 5]
+    let add_3 = wrapping_qadd_const(&[0, 1, 2], 3).remap(&|q| q + 3).unwrap();
+    let q_out = add_3.apply(Qubits::from_num(6, 2 << 3));
+    assert_eq!(q_out.pop_most_plausible(), 5 << 3);
+    ```
+     */
+    fn remap(&self, _mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        None
+    }
+}
+
+impl Clone for Box<dyn Operator> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
 
 /**
 Obtain the observed bit string from the probability distribution extracted from the measure function
@@ -406,8 +778,8 @@ pub fn pop_from_probs(probs: &[f64], size: usize) -> usize {
 
     loop {
         let mut r: f64 = rand::thread_rng().gen();
-        for i in 0..(1 << size) {
-            r -= probs[i];
+        for (i, p) in probs.iter().take(1 << size).enumerate() {
+            r -= p;
             if r < 0.0 {
                 return i;
             }
@@ -13,9 +13,9 @@ pub fn mod_power(a: usize, exp: usize, m: usize) -> usize {
     }
 
     if exp % 2 == 1 {
-        return (a * mod_power((a * a) % m, exp / 2, m)) % m;
+        (a * mod_power((a * a) % m, exp / 2, m)) % m
     } else {
-        return mod_power((a * a) % m, exp / 2, m);
+        mod_power((a * a) % m, exp / 2, m)
     }
 }
 
@@ -25,12 +25,12 @@ fn ext_gcd(a: isize, b: isize, c: isize) -> (isize, isize) {
     A function to find an integer (X,Y) that satisfies aX + bY = c
     */
     if b == 0 {
-        return (a / c, 0);
+        (a / c, 0)
     } else {
         let r = a % b;
         let q = a / b;
         let (s, t) = ext_gcd(b, r, c);
-        return (t, s - q * t);
+        (t, s - q * t)
     }
 }
 
@@ -39,12 +39,12 @@ pub fn is_coprime(a: usize, b: usize) -> bool {
     Returns gcd(a, b) == 1
     */
     if b == 1 {
-        return true;
+        true
     } else {
-        if a % b == 0 {
+        if a.is_multiple_of(b) {
             return false;
         }
-        return is_coprime(b, a % b);
+        is_coprime(b, a % b)
     }
 }
 
@@ -62,5 +62,5 @@ pub fn mod_inv(a: usize, m: usize) -> usize {
             break;
         }
     }
-    return x as usize;
+    x as usize
 }
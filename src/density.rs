@@ -0,0 +1,284 @@
+/*!
+ Density-matrix simulator.
+
+ A [`DensityMatrix`] holds ρ as a 2^n x 2^n matrix, so it can describe mixed states: the result of
+ noise channels such as [`DensityMatrix::depolarize`] or of measurements whose outcome is discarded.
+ Memory grows as 4^n, so it is meant for about a dozen qubits.
+
+ [`QuantumState::apply`] maps ρ to UρU† with the same `apply` as [`Qubits`], so every operator of the
+ gates module can be used, including two-qubit [`MatrixGate`](crate::gates::MatrixGate)s.
+
+# Example
+```
+use Qit::core::QuantumState;
+use Qit::density::DensityMatrix;
+use Qit::gates::{CX, H};
+
+// Bell state, then full depolarizing noise on qubit 0
+let mut rho = DensityMatrix::zeros(2);
+rho.apply(&H::new(0)).unwrap();
+rho.apply(&CX::new(0, 1)).unwrap();
+assert!((rho.purity() - 1.0).abs() < 1e-9);
+
+rho.depolarize(0, 0.75);
+assert!((rho.purity() - 0.25).abs() < 1e-9);
+assert!((rho.trace() - 1.0).abs() < 1e-9);
+```
+*/
+
+use super::core::{
+    Applicable, Comp, ElementarySink, Matrix2, QuantumState, Qubits, UnsupportedGate,
+};
+
+const EPS: f64 = 1e-9;
+
+/**
+Density matrix of `size` qubits. `rho[row * 2^size + col]` is ⟨row|ρ|col⟩, with bit i of an index
+being qubit i as in [`Qubits`].
+*/
+#[derive(Clone)]
+pub struct DensityMatrix {
+    pub size: usize,
+    pub rho: Vec<Comp>,
+}
+
+impl DensityMatrix {
+    /**
+    |0...0⟩⟨0...0|
+    */
+    pub fn zeros(size: usize) -> Self {
+        DensityMatrix::from_num(size, 0)
+    }
+
+    /**
+    |number⟩⟨number|
+    */
+    pub fn from_num(size: usize, number: usize) -> Self {
+        DensityMatrix::from_qubits(&Qubits::from_num(size, number))
+    }
+
+    /**
+    The pure state |ψ⟩⟨ψ|.
+    */
+    pub fn from_qubits(qubits: &Qubits) -> Self {
+        let mut rho = Vec::with_capacity(qubits.bits.len() * qubits.bits.len());
+        for a in qubits.bits.iter() {
+            for b in qubits.bits.iter() {
+                rho.push(*a * conj(*b));
+            }
+        }
+        DensityMatrix {
+            size: qubits.size,
+            rho,
+        }
+    }
+
+    /**
+    ⟨row|ρ|col⟩
+    */
+    pub fn element(&self, row: usize, col: usize) -> Comp {
+        self.rho[row * self.dim() + col]
+    }
+
+    /**
+    Tr ρ, which stays 1 under unitaries, channels and measurements.
+    */
+    pub fn trace(&self) -> f64 {
+        (0..self.dim()).map(|i| self.element(i, i).0).sum()
+    }
+
+    /**
+    Tr ρ², 1 for pure states and 1/2^size for the maximally mixed state.
+    */
+    pub fn purity(&self) -> f64 {
+        let dim = self.dim();
+        let mut purity = 0.0;
+        for row in 0..dim {
+            for col in 0..dim {
+                // ρ is Hermitian, so (ρ²)_ii = Σ_j |ρ_ij|²
+                purity += self.element(row, col).abs_square();
+            }
+        }
+        purity
+    }
+
+    /**
+    Apply the channel ρ → Σ K ρ K† on `target`. Panics unless Σ K†K = I.
+    */
+    pub fn apply_channel(&mut self, target: usize, kraus: &[Matrix2]) {
+        assert!(target < self.size);
+        for i in 0..2 {
+            for j in 0..2 {
+                let mut sum = Comp::zero();
+                for k in kraus.iter() {
+                    sum = sum + conj(k[0][i]) * k[0][j] + conj(k[1][i]) * k[1][j];
+                }
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!(
+                    (sum - Comp(expected, 0.0)).abs_square() < EPS,
+                    "Kraus operators are not trace preserving"
+                );
+            }
+        }
+        let mut rho = vec![Comp::zero(); self.rho.len()];
+        for k in kraus.iter() {
+            let mut term = self.clone();
+            term.conjugate(target, k, &[]);
+            for (r, t) in rho.iter_mut().zip(term.rho.iter()) {
+                *r = *r + *t;
+            }
+        }
+        self.rho = rho;
+    }
+
+    /**
+    Depolarizing channel ρ → (1 - p)ρ + p/3 (XρX + YρY + ZρZ) on `target`.
+    p = 3/4 replaces the qubit by the maximally mixed state.
+    */
+    pub fn depolarize(&mut self, target: usize, p: f64) {
+        assert!((0.0..=1.0).contains(&p));
+        let (o, l, i) = (Comp::zero(), Comp(1.0, 0.0), Comp(0.0, 1.0));
+        let a = (1.0 - p).sqrt();
+        let b = (p / 3.0).sqrt();
+        let kraus = [
+            [[l * a, o], [o, l * a]],
+            [[o, l * b], [l * b, o]],
+            [[o, i * -b], [i * b, o]],
+            [[l * b, o], [o, l * -b]],
+        ];
+        self.apply_channel(target, &kraus);
+    }
+
+    /**
+    Measure `target` drawing the outcome from `rng`, collapsing ρ onto it.
+    */
+    pub fn measure_with(&mut self, target: usize, rng: &mut impl rand::Rng) -> bool {
+        let p1 = self.marginal_probs(&[target])[1];
+        let outcome = rng.gen::<f64>() < p1;
+        let p = if outcome { p1 } else { 1.0 - p1 };
+        let dim = self.dim();
+        for row in 0..dim {
+            for col in 0..dim {
+                let keep =
+                    ((row >> target) & 1 == 1) == outcome && ((col >> target) & 1 == 1) == outcome;
+                let x = &mut self.rho[row * dim + col];
+                *x = if keep { *x * (1.0 / p) } else { Comp::zero() };
+            }
+        }
+        outcome
+    }
+
+    fn dim(&self) -> usize {
+        1 << self.size
+    }
+
+    /**
+    ρ → MρM† for `matrix` on `target`, controlled by `controls`.
+    */
+    fn conjugate(&mut self, target: usize, matrix: &Matrix2, controls: &[usize]) {
+        assert!(target < self.size);
+        let step = 1 << target;
+        let mut mask = 0;
+        for c in controls {
+            assert!(*c < self.size);
+            mask |= 1 << c;
+        }
+        assert!(mask & step == 0, "target bit is also a control bit");
+        let dim = self.dim();
+        let pairs: Vec<usize> = (0..dim)
+            .filter(|i| i & mask == mask && i & step == 0)
+            .collect();
+        // M from the left, on the row index
+        for col in 0..dim {
+            for i0 in pairs.iter() {
+                let (a0, a1) = (self.element(*i0, col), self.element(i0 | step, col));
+                self.rho[i0 * dim + col] = matrix[0][0] * a0 + matrix[0][1] * a1;
+                self.rho[(i0 | step) * dim + col] = matrix[1][0] * a0 + matrix[1][1] * a1;
+            }
+        }
+        // M† from the right, on the column index
+        for row in 0..dim {
+            for j0 in pairs.iter() {
+                let (a0, a1) = (self.element(row, *j0), self.element(row, j0 | step));
+                self.rho[row * dim + j0] = a0 * conj(matrix[0][0]) + a1 * conj(matrix[0][1]);
+                self.rho[row * dim + (j0 | step)] =
+                    a0 * conj(matrix[1][0]) + a1 * conj(matrix[1][1]);
+            }
+        }
+    }
+}
+
+impl ElementarySink for DensityMatrix {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        self.conjugate(target, matrix, controls);
+        Ok(())
+    }
+}
+
+impl QuantumState for DensityMatrix {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    /**
+    UρU† = (U(Uρ)†)†, where U is applied to each column of the matrix like to [`Qubits`].
+    */
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let dim = self.dim();
+        let u_rho = apply_to_columns(op, self.size, &self.rho);
+        let u_rho_u = apply_to_columns(op, self.size, &dagger(&u_rho, dim));
+        self.rho = dagger(&u_rho_u, dim);
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        self.measure_with(target, &mut rand::thread_rng())
+    }
+
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        let mut probs = vec![0.0; 1 << tar.len()];
+        for index in 0..self.dim() {
+            let mut tar_idx = 0;
+            for (j, t) in tar.iter().enumerate() {
+                tar_idx |= (1 & (index >> t)) << j;
+            }
+            probs[tar_idx] += self.element(index, index).0;
+        }
+        probs
+    }
+}
+
+/**
+`op` applied to every column of the row-major matrix `m`.
+*/
+fn apply_to_columns(op: &dyn Applicable, size: usize, m: &[Comp]) -> Vec<Comp> {
+    let dim = 1 << size;
+    let mut out = vec![Comp::zero(); dim * dim];
+    for col in 0..dim {
+        let column = (0..dim).map(|row| m[row * dim + col]).collect();
+        let q_out = op.apply(Qubits::from_bits(size, column));
+        for (row, amp) in q_out.bits.iter().enumerate() {
+            out[row * dim + col] = *amp;
+        }
+    }
+    out
+}
+
+fn dagger(m: &[Comp], dim: usize) -> Vec<Comp> {
+    let mut out = vec![Comp::zero(); dim * dim];
+    for row in 0..dim {
+        for col in 0..dim {
+            out[col * dim + row] = conj(m[row * dim + col]);
+        }
+    }
+    out
+}
+
+fn conj(c: Comp) -> Comp {
+    Comp(c.0, -c.1)
+}
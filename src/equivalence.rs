@@ -0,0 +1,180 @@
+/*!
+ Equivalence checking of two operators acting on the same qubits.
+
+ * [`equivalent_unitary`] compares the full matrices up to a global phase. Exact, but it costs 2^n
+   applications of each operator, so it is meant for small widths.
+ * [`equivalent_random`] compares the operators on random states, up to a global phase. Operators that
+   differ are told apart by any generic state, so a few trials suffice for medium widths.
+ * [`equivalent_permutation`] compares reversible circuits bit by bit with
+   [`run_classical`], which works for circuits far too wide for a state vector. Phases are ignored.
+
+ [`equivalent`] picks between the first two according to the width.
+
+# Example
+```
+use Qit::circuits::wrapping_qadd_const;
+use Qit::equivalence::{equivalent, equivalent_permutation};
+use Qit::gates::U;
+
+let b = vec![0, 1, 2, 3];
+let add_5 = wrapping_qadd_const(&b, 5);
+let add_2_3 = U::new(
+    vec![
+        Box::new(wrapping_qadd_const(&b, 2)),
+        Box::new(wrapping_qadd_const(&b, 3)),
+    ],
+    String::from("add_2_3"),
+);
+assert!(equivalent(&add_5, &add_2_3, 4));
+
+let inputs: Vec<u128> = (0..16).collect();
+assert_eq!(equivalent_permutation(&add_5, &add_2_3, 4, &inputs), Ok(true));
+assert_eq!(
+    equivalent_permutation(&add_5, &wrapping_qadd_const(&b, 4), 4, &inputs),
+    Ok(false)
+);
+```
+*/
+
+use rand::prelude::*;
+
+use super::classical::run_classical;
+use super::core::{Applicable, Comp, Operator, Qubits, UnsupportedGate};
+use super::unitary::unitary;
+
+const EPS: f64 = 1e-9;
+
+/**
+Widths up to which [`equivalent`] compares full unitaries.
+ */
+pub const UNITARY_MAX_QUBITS: usize = 8;
+
+/**
+Number of random states used by [`equivalent`] above [`UNITARY_MAX_QUBITS`].
+ */
+pub const RANDOM_TRIALS: usize = 4;
+
+/**
+Seed of the random states used by [`equivalent`], so that its result is reproducible.
+ */
+pub const RANDOM_SEED: u64 = 49;
+
+/**
+Check that `a` and `b` are equal up to a global phase on `n_qubits` qubits, by [`equivalent_unitary`]
+for at most [`UNITARY_MAX_QUBITS`] qubits and by [`equivalent_random`] with [`RANDOM_SEED`]
+otherwise.
+ */
+pub fn equivalent(a: &dyn Operator, b: &dyn Operator, n_qubits: usize) -> bool {
+    if n_qubits <= UNITARY_MAX_QUBITS {
+        return equivalent_unitary(a, b, n_qubits);
+    }
+    equivalent_random(a, b, n_qubits, RANDOM_TRIALS, Some(RANDOM_SEED))
+}
+
+/**
+Check B = exp(iφ)A for the matrices of `a` and `b` on `n_qubits` qubits, entry by entry within 1e-9.
+ */
+pub fn equivalent_unitary(a: &dyn Operator, b: &dyn Operator, n_qubits: usize) -> bool {
+    let ua = unitary(a, n_qubits);
+    let ub = unitary(b, n_qubits);
+
+    // the global phase is fixed by the largest entry of A
+    let mut pivot = (0, 0);
+    for (i, row) in ua.iter().enumerate() {
+        for (j, x) in row.iter().enumerate() {
+            if x.abs_square() > ua[pivot.0][pivot.1].abs_square() {
+                pivot = (i, j);
+            }
+        }
+    }
+    let x = ua[pivot.0][pivot.1];
+    let y = ub[pivot.0][pivot.1];
+    let phase = Comp(x.0, -x.1) * y * (1.0 / x.abs_square());
+    if (phase.abs_square() - 1.0).abs() > EPS {
+        return false;
+    }
+
+    ua.iter()
+        .zip(ub.iter())
+        .all(|(row_a, row_b)| equal_up_to(row_a, row_b, phase))
+}
+
+/**
+Check B|ψ⟩ = exp(iφ)A|ψ⟩ for `trials` random states |ψ⟩ of `n_qubits` qubits, with the phase φ
+taken from ⟨ψ|A†B|ψ⟩.
+
+The amplitudes are compared one by one within 1e-9, the same tolerance that [`equivalent_unitary`]
+applies to matrix entries. A single random state already tells apart operators that differ by more
+than a global phase, except with probability zero; further trials guard against numerically unlucky
+states. Pass `seed` for a reproducible check.
+ */
+pub fn equivalent_random(
+    a: &dyn Operator,
+    b: &dyn Operator,
+    n_qubits: usize,
+    trials: usize,
+    seed: Option<u64>,
+) -> bool {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    for _ in 0..trials {
+        let psi = random_state(&mut rng, n_qubits);
+        let qa = a.apply(psi.clone());
+        let qb = b.apply(psi);
+        let mut overlap = Comp::zero();
+        for (x, y) in qa.bits.iter().zip(qb.bits.iter()) {
+            overlap = overlap + Comp(x.0, -x.1) * *y;
+        }
+        let norm = overlap.abs_square().sqrt();
+        if norm < EPS {
+            return false;
+        }
+        if !equal_up_to(&qa.bits, &qb.bits, overlap * (1.0 / norm)) {
+            return false;
+        }
+    }
+    true
+}
+
+/**
+Whether b = phase * a, entry by entry within [`EPS`].
+ */
+fn equal_up_to(a: &[Comp], b: &[Comp], phase: Comp) -> bool {
+    a.iter()
+        .zip(b.iter())
+        .all(|(x, y)| (*y - *x * phase).abs_square() <= EPS * EPS)
+}
+
+/**
+Check that the reversible circuits `a` and `b` on `size` qubits map every basis state in `inputs` to
+the same basis state. Pass all of 0..2^size for an exhaustive check.
+
+Diagonal gates are ignored, so circuits that differ only in phases compare equal. Returns an error if
+either circuit contains a gate that [`run_classical`] does not accept.
+ */
+pub fn equivalent_permutation(
+    a: &dyn Applicable,
+    b: &dyn Applicable,
+    size: usize,
+    inputs: &[u128],
+) -> Result<bool, UnsupportedGate> {
+    for input in inputs.iter() {
+        if run_classical(a, size, *input)? != run_classical(b, size, *input)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn random_state(rng: &mut StdRng, n_qubits: usize) -> Qubits {
+    let mut bits: Vec<Comp> = (0..1 << n_qubits)
+        .map(|_| Comp(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)))
+        .collect();
+    let norm: f64 = bits.iter().map(|x| x.abs_square()).sum::<f64>().sqrt();
+    for x in bits.iter_mut() {
+        *x = *x * (1.0 / norm);
+    }
+    Qubits::from_bits(n_qubits, bits)
+}
@@ -9,12 +9,27 @@
     * Z(Z-Gate)
     * H(Hadamard-Gate)
     * R(R_z-Gate. Gate that rotates at any angle around the z-axis)
+    * PhaseGate(S, Sdg, T and Tdg: R(π/2), R(π/4) and their inverses)
+    * SX(√X Gate)
+    * RX, RY, RZ(Rotations around the x, y and z axes)
+    * U3(General single-qubit gate U3(θ, φ, λ))
 * 2-Bit Gate
     * CX(Controlled Not Gate)
+    * CZ(Controlled Z Gate)
+    * CPhase(Controlled phase shift Gate)
+    * SWAP, ISWAP(Gates that exchange two qubits)
 * 3-Bit Gate
     * CCX(Controlled Controlled Not Gate)
+    * CSWAP(Controlled SWAP Gate. Fredkin Gate)
 * N-Bit Gate
     * CNX(Controlled Controlled ...(n) Not Gate)
+    * MCZ(Multi-Controlled Z Gate)
+    * PauliRotation(Rotation exp(-iθP/2) generated by a Pauli string P)
+    * PhaseOracle(Diagonal oracle exp(iφ(x)) given by a closure)
+    * PermutationOracle(Permutation of basis states given by a closure)
+    * Pow(An operator applied k times)
+* 1 or 2-Bit Gate
+    * MatrixGate(Gate given by an explicit 2x2 or 4x4 unitary matrix)
 
 CNX is an X gate with an arbitrary number of control bits prepared for convenience in circuit creation.
 
@@ -144,11 +159,266 @@ assert_eq!(q_out.bits[0b1111], Comp::new(1.0, 0.0));
 */
 
 use std::f64::consts::{PI, SQRT_2};
+use std::sync::Arc;
 
-use super::core::{Applicable, BitSlideIndex, Comp, Inversible, Operator, Qubits};
+use super::core::{
+    Applicable, BitSlideIndex, Comp, ElementarySink, GateInfo, GateKind, Inversible, Matrix2,
+    Operator, OperatorClone, Qubits, UnsupportedGate,
+};
+use super::pauli::{Pauli, PauliString};
+use super::unitary::is_unitary;
 
 const SQRT2_INV: f64 = 1.0 / SQRT_2;
 
+const ZERO: Comp = Comp(0.0, 0.0);
+const ONE: Comp = Comp(1.0, 0.0);
+const H_MATRIX: Matrix2 = [
+    [Comp(SQRT2_INV, 0.0), Comp(SQRT2_INV, 0.0)],
+    [Comp(SQRT2_INV, 0.0), Comp(-SQRT2_INV, 0.0)],
+];
+const X_MATRIX: Matrix2 = [[ZERO, ONE], [ONE, ZERO]];
+const Y_MATRIX: Matrix2 = [[ZERO, Comp(0.0, 1.0)], [Comp(0.0, -1.0), ZERO]];
+const Z_MATRIX: Matrix2 = [[ONE, ZERO], [ZERO, Comp(-1.0, 0.0)]];
+
+/**
+Multiply the amplitudes of |1⟩ on `target` by `phase`.
+ */
+fn apply_phase(mut qubits: Qubits, iter: &BitSlideIndex, target: usize, phase: Comp) -> Qubits {
+    let iter = iter.merge(1 << target);
+    for idx1 in iter {
+        qubits.bits[idx1] = qubits.bits[idx1] * phase;
+    }
+    qubits
+}
+
+/**
+Apply a single-qubit matrix to `target` for every index pair enumerated by `iter`.
+ */
+fn apply_matrix(mut qubits: Qubits, iter: &BitSlideIndex, target: usize, m: &Matrix2) -> Qubits {
+    let step = 1 << target;
+    let iter = iter.merge(step);
+    for idx1 in iter {
+        let idx0 = idx1 - step;
+        let (a0, a1) = (qubits.bits[idx0], qubits.bits[idx1]);
+        qubits.bits[idx0] = m[0][0] * a0 + m[0][1] * a1;
+        qubits.bits[idx1] = m[1][0] * a0 + m[1][1] * a1;
+    }
+    qubits
+}
+
+/**
+Qubits of one gate after remapping. They must stay distinct.
+ */
+fn remap_bits(bits: &[usize], mapping: &dyn Fn(usize) -> usize) -> Vec<usize> {
+    let mapped: Vec<usize> = bits.iter().map(|b| mapping(*b)).collect();
+    for i in 0..mapped.len() {
+        for j in 0..i {
+            assert_ne!(
+                mapped[i], mapped[j],
+                "mapping sends qubits {} and {} of one gate to the same index",
+                bits[j], bits[i]
+            );
+        }
+    }
+    mapped
+}
+
+/**
+Whether the gate is diagonal in the computational basis. Diagonal gates commute with each other.
+ */
+fn is_diagonal(info: &GateInfo) -> bool {
+    match info.kind {
+        GateKind::Z
+        | GateKind::R
+        | GateKind::S
+        | GateKind::Sdg
+        | GateKind::T
+        | GateKind::Tdg
+        | GateKind::RZ
+        | GateKind::CZ
+        | GateKind::CPhase
+        | GateKind::MCZ
+        | GateKind::PhaseOracle => true,
+        GateKind::Matrix => info.matrix.as_ref().is_some_and(|m| {
+            m.iter()
+                .enumerate()
+                .all(|(i, row)| row.iter().enumerate().all(|(j, x)| i == j || *x == ZERO))
+        }),
+        GateKind::U | GateKind::CU | GateKind::Pow => info.children.iter().all(is_diagonal),
+        _ => false,
+    }
+}
+
+/**
+Whether the gate or one of its children is opaque without reporting any qubit, so its qubits are
+unknown.
+ */
+fn is_opaque(info: &GateInfo) -> bool {
+    let unknown =
+        info.kind == GateKind::Opaque && info.targets.is_empty() && info.controls.is_empty();
+    unknown || info.children.iter().any(is_opaque)
+}
+
+/**
+`Operator::remap` of a `Copy` gate whose only qubit is `target_bit`.
+ */
+macro_rules! remap_target_bit {
+    () => {
+        fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+            let mut gate = *self;
+            gate.target_bit = mapping(self.target_bit);
+            Some(Box::new(gate))
+        }
+    };
+}
+
+/**
+Even powers of a self-inverse gate are the identity, odd powers the gate itself.
+ */
+fn involution_power(gate: &dyn Operator, k: usize) -> Option<Box<dyn Operator>> {
+    if k.is_multiple_of(2) {
+        return Some(Box::new(U::new(
+            Vec::new(),
+            format!("{}^{}", gate.name(), k),
+        )));
+    }
+    Some(gate.box_clone())
+}
+
+/**
+Powers of a gate G with G^4 = I: the identity, G, `square` = G^2 or G†.
+ */
+fn period4_power(
+    gate: &dyn Operator,
+    square: Box<dyn Operator>,
+    k: usize,
+) -> Option<Box<dyn Operator>> {
+    match k % 4 {
+        0 => Some(Box::new(U::new(
+            Vec::new(),
+            format!("{}^{}", gate.name(), k),
+        ))),
+        1 => Some(gate.box_clone()),
+        2 => Some(square),
+        _ => Some(gate.dagger()),
+    }
+}
+
+fn matmul(a: &[Vec<Comp>], b: &[Vec<Comp>]) -> Vec<Vec<Comp>> {
+    let dim = a.len();
+    let mut c = vec![vec![ZERO; dim]; dim];
+    for i in 0..dim {
+        for j in 0..dim {
+            for l in 0..dim {
+                c[i][j] = c[i][j] + a[i][l] * b[l][j];
+            }
+        }
+    }
+    c
+}
+
+/**
+table applied k times, by repeated squaring.
+ */
+fn permutation_power(table: &[usize], k: usize) -> Vec<usize> {
+    let mut result: Vec<usize> = (0..table.len()).collect();
+    let mut base = table.to_vec();
+    let mut e = k;
+    while e > 0 {
+        if e & 1 == 1 {
+            result = result.iter().map(|x| base[*x]).collect();
+        }
+        base = base.iter().map(|x| base[*x]).collect();
+        e >>= 1;
+    }
+    result
+}
+
+/**
+Exchange the amplitudes of |..1..0..⟩ and |..0..1..⟩ on (a, b), multiplying both by `phase`.
+ */
+fn swap_amplitudes(
+    mut qubits: Qubits,
+    iter: &BitSlideIndex,
+    a: usize,
+    b: usize,
+    phase: Comp,
+) -> Qubits {
+    let (step_a, step_b) = (1 << a, 1 << b);
+    let iter = iter.merge(step_a | step_b);
+    for idx11 in iter {
+        let idx01 = idx11 - step_b;
+        let idx10 = idx11 - step_a;
+        let temp = qubits.bits[idx01];
+        qubits.bits[idx01] = qubits.bits[idx10] * phase;
+        qubits.bits[idx10] = temp * phase;
+    }
+    qubits
+}
+
+/**
+Mask of the control bits and the value they must take for the gate to fire.
+
+A control with polarity `false` fires when its bit is |0⟩.
+ */
+fn control_pattern(bits: &[usize], polarities: &[bool]) -> (usize, usize) {
+    assert_eq!(bits.len(), polarities.len());
+    let (mut mask, mut value) = (0, 0);
+    for (bit, polarity) in bits.iter().zip(polarities.iter()) {
+        mask |= 1 << bit;
+        if *polarity {
+            value |= 1 << bit;
+        }
+    }
+    (mask, value)
+}
+
+/**
+Control bit as written in gate names. Negative controls are prefixed with "!".
+ */
+fn control_label(bit: usize, polarity: bool) -> String {
+    if polarity {
+        return format!("{}", bit);
+    }
+    format!("!{}", bit)
+}
+
+/**
+Apply X to every negative control, so that lowering can treat all controls as positive.
+
+Called before and after the controlled part; the two X gates cancel when the gate does not fire.
+ */
+fn flip_negative_controls(
+    gate: &dyn Applicable,
+    sink: &mut dyn ElementarySink,
+    bits: &[usize],
+    polarities: &[bool],
+) -> Result<(), UnsupportedGate> {
+    for (bit, polarity) in bits.iter().zip(polarities.iter()) {
+        if !*polarity {
+            lower_matrix(gate, sink, *bit, X_MATRIX, &[], &[])?;
+        }
+    }
+    Ok(())
+}
+
+/**
+Pass a single-qubit matrix to `sink` with the gate's own control bits appended to the enclosing ones.
+ */
+fn lower_matrix(
+    gate: &dyn Applicable,
+    sink: &mut dyn ElementarySink,
+    target: usize,
+    matrix: Matrix2,
+    controls: &[usize],
+    own_controls: &[usize],
+) -> Result<(), UnsupportedGate> {
+    let mut all_controls = controls.to_vec();
+    all_controls.extend_from_slice(own_controls);
+    sink.apply_controlled(target, &matrix, &all_controls)
+        .map_err(|reason| UnsupportedGate::new(gate.name(), &reason))
+}
+
 /**
  * Hadamard Gate. 1√2(|0⟩⟨0| + |1⟩⟨0| + |0⟩⟨1| - |1⟩⟨1|)
 
@@ -175,15 +445,17 @@ pub struct H {
 
 impl H {
     pub fn new(target_bit: usize) -> Self {
-        return H {
-            target_bit: target_bit,
-        };
+        H { target_bit }
     }
 }
 
 impl Applicable for H {
     fn name(&self) -> String {
-        return format!("H({})", self.target_bit);
+        format!("H({})", self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(GateKind::H, vec![self.target_bit], vec![], vec![])
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
@@ -197,12 +469,30 @@ impl Applicable for H {
             qubits.bits[idx1] = (temp - qubits.bits[idx1]) * SQRT2_INV;
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, H_MATRIX, controls, &[])
+    }
+}
+
+impl Inversible for H {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
     }
 }
+impl Operator for H {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
 
-impl Inversible for H {}
-impl Operator for H {}
+    remap_target_bit!();
+}
 
 /**
 Not Gate(pauli-X). (|1⟩⟨0| + |0⟩⟨1|)
@@ -228,15 +518,17 @@ pub struct X {
 }
 impl X {
     pub fn new(target_bit: usize) -> Self {
-        return X {
-            target_bit: target_bit,
-        };
+        X { target_bit }
     }
 }
 
 impl Applicable for X {
     fn name(&self) -> String {
-        return format!("X({})", self.target_bit);
+        format!("X({})", self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(GateKind::X, vec![self.target_bit], vec![], vec![])
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
@@ -245,17 +537,33 @@ impl Applicable for X {
 
         for idx1 in iter {
             let idx0 = idx1 - step;
-            let temp = qubits.bits[idx0];
-            qubits.bits[idx0] = qubits.bits[idx1];
-            qubits.bits[idx1] = temp;
+            qubits.bits.swap(idx0, idx1);
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, X_MATRIX, controls, &[])
     }
 }
 
-impl Inversible for X {}
-impl Operator for X {}
+impl Inversible for X {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for X {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    remap_target_bit!();
+}
 
 /**
  pauli-Y Gate. i(|1⟩⟨0| - |0⟩⟨1|)
@@ -282,15 +590,17 @@ pub struct Y {
 
 impl Y {
     pub fn new(target_bit: usize) -> Self {
-        return Y {
-            target_bit: target_bit,
-        };
+        Y { target_bit }
     }
 }
 
 impl Applicable for Y {
     fn name(&self) -> String {
-        return format!("Y({})", self.target_bit);
+        format!("Y({})", self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(GateKind::Y, vec![self.target_bit], vec![], vec![])
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
@@ -304,12 +614,30 @@ impl Applicable for Y {
             qubits.bits[idx1] = Comp::new(0.0, -1.0) * temp;
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, Y_MATRIX, controls, &[])
+    }
+}
+
+impl Inversible for Y {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
     }
 }
+impl Operator for Y {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
 
-impl Inversible for Y {}
-impl Operator for Y {}
+    remap_target_bit!();
+}
 
 /**
  pauli-Z Gate. (|0⟩⟨0| - |1⟩⟨1|)
@@ -337,15 +665,17 @@ pub struct Z {
 
 impl Z {
     pub fn new(target_bit: usize) -> Self {
-        return Z {
-            target_bit: target_bit,
-        };
+        Z { target_bit }
     }
 }
 
 impl Applicable for Z {
     fn name(&self) -> String {
-        return format!("Z({})", self.target_bit);
+        format!("Z({})", self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(GateKind::Z, vec![self.target_bit], vec![], vec![])
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
@@ -356,12 +686,30 @@ impl Applicable for Z {
             qubits.bits[idx1] = qubits.bits[idx1] * -1.0;
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, Z_MATRIX, controls, &[])
     }
 }
 
-impl Inversible for Z {}
-impl Operator for Z {}
+impl Inversible for Z {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for Z {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    remap_target_bit!();
+}
 
 /**
 Phase shift Gate. (|0⟩⟨0| + exp(ir)|1⟩⟨1|)
@@ -397,17 +745,21 @@ pub struct R {
 impl R {
     pub fn new(target_bit: usize, angle: f64) -> Self {
         let phase = Comp(angle.cos(), angle.sin());
-        return R {
-            target_bit: target_bit,
-            angle: angle,
-            phase: phase,
-        };
+        R {
+            target_bit,
+            angle,
+            phase,
+        }
     }
 }
 
 impl Applicable for R {
     fn name(&self) -> String {
-        return format!("R_{}({})", self.angle, self.target_bit);
+        format!("R_{}({})", self.angle, self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(GateKind::R, vec![self.target_bit], vec![], vec![self.angle])
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
@@ -417,215 +769,2359 @@ impl Applicable for R {
             qubits.bits[idx1] = qubits.bits[idx1] * self.phase;
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(
+            self,
+            sink,
+            self.target_bit,
+            [[ONE, ZERO], [ZERO, self.phase]],
+            controls,
+            &[],
+        )
     }
 }
 
 impl Inversible for R {
-    fn inverse(&mut self) {
-        self.angle = 2.0 * PI - self.angle;
-        self.phase = Comp(self.angle.cos(), self.angle.sin());
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(R::new(self.target_bit, 2.0 * PI - self.angle))
+    }
+}
+impl Operator for R {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(R::new(self.target_bit, self.angle * k as f64)))
     }
+
+    remap_target_bit!();
 }
-impl Operator for R {}
 
 /**
-Controlled-Not Gate.
+Phase gate (|0⟩⟨0| + exp(iπk/4)|1⟩⟨1|) for k = 1, 2, 6, 7, that is T, S, S† and T†.
 
-# Usage
+S, T and their inverses are one type, built by [`PhaseGate::s`], [`PhaseGate::sdg`],
+[`PhaseGate::t`] and [`PhaseGate::tdg`], and told apart by the [`GateKind`] of `describe`.
+`inverse` maps k to 8 - k, so the name and the kind always follow the phase actually applied.
 
+# Usage
 ```
-use Qit::core::{Applicable, Qubits, Comp};
-use Qit::gates::CX;
-
-// create CX(0 → 1)
-let cx01 = CX::new(0, 1);
-let q_in = Qubits::from_num(2, 1);
-let q_out = cx01.apply(q_in);
-q_out.print_cmps();
-// |00⟩ : +0.000 +0.000i
-// |01⟩ : +0.000 +0.000i
-// |10⟩ : +0.000 +0.000i
-// |11⟩ : +1.000 +0.000i
-assert_eq!(q_out.bits[0b11], Comp::new(1.0, 0.0));
+use Qit::{gates::PhaseGate, core::{Applicable, Inversible, Qubits}};
+
+let mut s_0 = PhaseGate::s(0);
+s_0.inverse();
+assert_eq!(s_0.name(), "Sdg(0)");
+let q_s = s_0.apply(Qubits::from_num(1, 1));
+let q_sdg = PhaseGate::sdg(0).apply(Qubits::from_num(1, 1));
+assert!((q_s.bits[1] - q_sdg.bits[1]).abs_square() < 1e-12);
 ```
  */
 #[derive(Clone, Copy)]
-pub struct CX {
-    controll_bit: usize,
+pub struct PhaseGate {
     target_bit: usize,
+    eighths: u8,
 }
 
-impl CX {
-    pub fn new(controll_bit: usize, target_bit: usize) -> Self {
-        return CX {
-            controll_bit: controll_bit,
-            target_bit: target_bit,
-        };
+impl PhaseGate {
+    fn new(target_bit: usize, eighths: u8) -> Self {
+        PhaseGate {
+            target_bit,
+            eighths,
+        }
     }
-}
 
-impl Applicable for CX {
-    fn name(&self) -> String {
-        return format!("CX({}->{})", self.controll_bit, self.target_bit);
+    /**
+    S Gate. (|0⟩⟨0| + i|1⟩⟨1|), equal to R(π/2).
+
+    # Usage
+    ```
+    use Qit::{gates::PhaseGate, core::{Applicable, Qubits, Comp}};
+
+    let s_0 = PhaseGate::s(0);
+    let q_out = s_0.apply(Qubits::from_num(1, 1));
+    assert_eq!(q_out.bits[1], Comp::new(0.0, 1.0));
+    ```
+     */
+    pub fn s(target_bit: usize) -> Self {
+        PhaseGate::new(target_bit, 2)
     }
-    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
-        let step = 1 << self.target_bit;
-        let iter = iter.merge((1 << self.controll_bit) | step);
 
-        for idx1 in iter {
-            let idx0 = idx1 - step;
-            let temp = qubits.bits[idx0];
-            qubits.bits[idx0] = qubits.bits[idx1];
-            qubits.bits[idx1] = temp;
-        }
+    /**
+    S† Gate. (|0⟩⟨0| - i|1⟩⟨1|), the inverse of [`PhaseGate::s`].
+
+    # Usage
+    ```
+    use Qit::{gates::PhaseGate, core::{Applicable, Qubits, Comp}};
 
-        return qubits;
+    let sdg_0 = PhaseGate::sdg(0);
+    let q_out = sdg_0.apply(Qubits::from_num(1, 1));
+    assert_eq!(q_out.bits[1], Comp::new(0.0, -1.0));
+    ```
+     */
+    pub fn sdg(target_bit: usize) -> Self {
+        PhaseGate::new(target_bit, 6)
     }
-}
 
-impl Inversible for CX {}
-impl Operator for CX {}
+    /**
+    T Gate. (|0⟩⟨0| + exp(iπ/4)|1⟩⟨1|), equal to R(π/4).
+
+    # Usage
+    ```
+    use Qit::{gates::{PhaseGate, U}, core::{Applicable, Qubits}};
+
+    // T・T = S
+    let tt = U::new(
+        vec![Box::new(PhaseGate::t(0)), Box::new(PhaseGate::t(0))],
+        String::from("tt"),
+    );
+    let q_tt = tt.apply(Qubits::from_num(1, 1));
+    let q_s = PhaseGate::s(0).apply(Qubits::from_num(1, 1));
+    assert!((q_tt.bits[1] - q_s.bits[1]).abs_square() < 1e-12);
+    ```
+     */
+    pub fn t(target_bit: usize) -> Self {
+        PhaseGate::new(target_bit, 1)
+    }
 
-/**
-Controlled-Controlled-Not(CXX) Gate.
+    /**
+    T† Gate. (|0⟩⟨0| + exp(-iπ/4)|1⟩⟨1|), the inverse of [`PhaseGate::t`].
+
+    # Usage
+    ```
+    use Qit::{gates::{PhaseGate, U}, core::{Applicable, Qubits, Comp}};
+
+    let t_tdg = U::new(
+        vec![Box::new(PhaseGate::t(0)), Box::new(PhaseGate::tdg(0))],
+        String::from("id"),
+    );
+    let q_out = t_tdg.apply(Qubits::from_num(1, 1));
+    assert!((q_out.bits[1] - Comp::new(1.0, 0.0)).abs_square() < 1e-12);
+    ```
+     */
+    pub fn tdg(target_bit: usize) -> Self {
+        PhaseGate::new(target_bit, 7)
+    }
 
-# Usage
+    fn angle(&self) -> f64 {
+        // k = 6, 7 are the negative angles -π/2, -π/4
+        let k = self.eighths as f64;
+        let k = if k > 4.0 { k - 8.0 } else { k };
+        k * 0.25 * PI
+    }
 
-```
-use Qit::core::{Applicable, Comp, Qubits};
-use Qit::gates::CCX;
+    fn phase(&self) -> Comp {
+        match self.eighths {
+            1 => Comp(SQRT2_INV, SQRT2_INV),
+            2 => Comp(0.0, 1.0),
+            6 => Comp(0.0, -1.0),
+            _ => Comp(SQRT2_INV, -SQRT2_INV),
+        }
+    }
 
-let ccx = CCX::new(0, 1, 2);
-// q_in = |011⟩
-let q_in = Qubits::from_num(3, 3);
-let q_out = ccx.apply(q_in);
-q_out.print_cmps();
-// |000⟩ : +0.000 +0.000i
-// |001⟩ : +0.000 +0.000i
-// |010⟩ : +0.000 +0.000i
-// |011⟩ : +0.000 +0.000i
-// |100⟩ : +0.000 +0.000i
-// |101⟩ : +0.000 +0.000i
-// |110⟩ : +0.000 +0.000i
-// |111⟩ : +1.000 +0.000i
-assert_eq!(q_out.bits[0b111], Comp::new(1.0, 0.0));
-```
- */
-#[derive(Clone, Copy)]
-pub struct CCX {
-    controll_bit1: usize,
-    controll_bit2: usize,
-    target_bit: usize,
-}
+    fn kind(&self) -> GateKind {
+        match self.eighths {
+            1 => GateKind::T,
+            2 => GateKind::S,
+            6 => GateKind::Sdg,
+            _ => GateKind::Tdg,
+        }
+    }
 
-impl CCX {
-    pub fn new(controll_bit1: usize, controll_bit2: usize, target_bit: usize) -> Self {
-        return CCX {
-            controll_bit1: controll_bit1,
-            controll_bit2: controll_bit2,
-            target_bit: target_bit,
-        };
+    fn label(&self) -> &'static str {
+        match self.eighths {
+            1 => "T",
+            2 => "S",
+            6 => "Sdg",
+            _ => "Tdg",
+        }
     }
 }
 
-impl Applicable for CCX {
+impl Applicable for PhaseGate {
     fn name(&self) -> String {
-        return format!(
-            "CCX([{},{}]->{})",
-            self.controll_bit1, self.controll_bit2, self.target_bit
-        );
+        format!("{}({})", self.label(), self.target_bit)
     }
-    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
-        let step = 1 << self.target_bit;
-        let iter = iter.merge((1 << self.controll_bit1) | (1 << self.controll_bit2) | step);
 
-        for idx1 in iter {
-            let idx0 = idx1 - step;
-            let temp = qubits.bits[idx0];
-            qubits.bits[idx0] = qubits.bits[idx1];
-            qubits.bits[idx1] = temp;
-        }
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(self.kind(), vec![self.target_bit], vec![], vec![])
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_phase(qubits, iter, self.target_bit, self.phase())
+    }
 
-        return qubits;
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let matrix = [[ONE, ZERO], [ZERO, self.phase()]];
+        lower_matrix(self, sink, self.target_bit, matrix, controls, &[])
     }
 }
 
-impl Inversible for CCX {}
-impl Operator for CCX {}
+impl Inversible for PhaseGate {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(PhaseGate::new(self.target_bit, 8 - self.eighths))
+    }
+}
+impl Operator for PhaseGate {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(R::new(self.target_bit, self.angle() * k as f64)))
+    }
+
+    remap_target_bit!();
+}
 
 /**
-Controlled-Controlled-Controlled-...(N)-Not Gate
+√X Gate. 1/2((1+i)(|0⟩⟨0| + |1⟩⟨1|) + (1-i)(|1⟩⟨0| + |0⟩⟨1|)), so that SX・SX = X.
+
+Calling `inverse` turns this struct into SX† and its name into "SXdg".
 
 # Usage
 ```
-use Qit::core::{Applicable, Comp, Qubits};
-use Qit::gates::CNX;
+use Qit::{gates::{SX, U}, core::{Applicable, Qubits, Comp}};
 
-let cnx = CNX::new(vec![0, 1, 2], 3);
-// q_in = |0111⟩
-let q_in = Qubits::from_num(4, 7);
-let q_out = cnx.apply(q_in);
-q_out.print_cmps();
-// |0000⟩ : +0.000 +0.000i
-// |0001⟩ : +0.000 +0.000i
-//        .
-//        .
-//        .
-// |1110⟩ : +0.000 +0.000i
-// |1111⟩ : +1.000 +0.000i
-assert_eq!(q_out.bits[0b1111], Comp::new(1.0, 0.0));
+let sx_sx = U::new(vec![Box::new(SX::new(0)), Box::new(SX::new(0))], String::from("x"));
+let q_out = sx_sx.apply(Qubits::from_num(1, 0));
+assert!((q_out.bits[1] - Comp::new(1.0, 0.0)).abs_square() < 1e-12);
 ```
-*/
-#[derive(Clone)]
-pub struct CNX {
-    controll_bits: Vec<usize>,
+ */
+#[derive(Clone, Copy)]
+pub struct SX {
     target_bit: usize,
+    inversed: bool,
 }
 
-impl CNX {
-    pub fn new(controll_bits: Vec<usize>, target_bit: usize) -> Self {
-        return CNX {
-            controll_bits: controll_bits,
-            target_bit: target_bit,
-        };
+impl SX {
+    pub fn new(target_bit: usize) -> Self {
+        SX {
+            target_bit,
+            inversed: false,
+        }
     }
 
-    fn cbit_mask(&self) -> usize {
-        let mut mask = 0;
-        for cbit in self.controll_bits.iter() {
-            mask |= 1 << (*cbit);
-        }
-        return mask;
+    fn matrix(&self) -> Matrix2 {
+        let sign = if self.inversed { -1.0 } else { 1.0 };
+        let a = Comp(0.5, 0.5 * sign);
+        let b = Comp(0.5, -0.5 * sign);
+        [[a, b], [b, a]]
     }
 }
 
-impl Applicable for CNX {
+impl Applicable for SX {
     fn name(&self) -> String {
-        let mut s = String::from("CNX[");
-        for i in self.controll_bits.iter() {
-            s += &format!("{},", i);
-        }
-        s += &format!("]->{}", self.target_bit);
-        return s;
+        let label = if self.inversed { "SXdg" } else { "SX" };
+        format!("{}({})", label, self.target_bit)
     }
-    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            if self.inversed {
+                GateKind::SXdg
+            } else {
+                GateKind::SX
+            },
+            vec![self.target_bit],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_matrix(qubits, iter, self.target_bit, &self.matrix())
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, self.matrix(), controls, &[])
+    }
+}
+
+impl Inversible for SX {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(SX {
+            inversed: !self.inversed,
+            ..*self
+        })
+    }
+}
+impl Operator for SX {
+    /**
+    SX^2 = SX†^2 = X and SX^4 = I.
+     */
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        period4_power(self, Box::new(X::new(self.target_bit)), k)
+    }
+
+    remap_target_bit!();
+}
+
+/**
+Rotation around the x-axis. exp(-iθX/2) = cos(θ/2)I - i sin(θ/2)X
+
+# Usage
+```
+use Qit::{gates::RX, core::{Applicable, Qubits}};
+use std::f64::consts::PI;
+
+// RX(π) flips |0⟩ to -i|1⟩
+let q_out = RX::new(0, PI).apply(Qubits::zeros(1));
+assert!((q_out.bits[1].1 + 1.0).abs() < 1e-12);
+```
+ */
+#[derive(Clone, Copy)]
+pub struct RX {
+    target_bit: usize,
+    angle: f64,
+}
+
+impl RX {
+    pub fn new(target_bit: usize, angle: f64) -> Self {
+        RX { target_bit, angle }
+    }
+
+    fn matrix(&self) -> Matrix2 {
+        let (c, s) = ((self.angle / 2.0).cos(), (self.angle / 2.0).sin());
+        [[Comp(c, 0.0), Comp(0.0, -s)], [Comp(0.0, -s), Comp(c, 0.0)]]
+    }
+}
+
+impl Applicable for RX {
+    fn name(&self) -> String {
+        format!("RX_{}({})", self.angle, self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::RX,
+            vec![self.target_bit],
+            vec![],
+            vec![self.angle],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_matrix(qubits, iter, self.target_bit, &self.matrix())
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, self.matrix(), controls, &[])
+    }
+}
+
+impl Inversible for RX {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(RX::new(self.target_bit, -self.angle))
+    }
+}
+impl Operator for RX {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(RX::new(self.target_bit, self.angle * k as f64)))
+    }
+
+    remap_target_bit!();
+}
+
+/**
+Rotation around the y-axis. exp(-iθY/2) = cos(θ/2)I - i sin(θ/2)Y
+
+# Usage
+```
+use Qit::{gates::RY, core::{Applicable, Qubits}};
+use std::f64::consts::PI;
+
+// RY(π/2)|0⟩ = (|0⟩ + |1⟩)/√2
+let q_out = RY::new(0, PI / 2.0).apply(Qubits::zeros(1));
+assert!((q_out.bits[0].0 - q_out.bits[1].0).abs() < 1e-12);
+```
+ */
+#[derive(Clone, Copy)]
+pub struct RY {
+    target_bit: usize,
+    angle: f64,
+}
+
+impl RY {
+    pub fn new(target_bit: usize, angle: f64) -> Self {
+        RY { target_bit, angle }
+    }
+
+    fn matrix(&self) -> Matrix2 {
+        let (c, s) = ((self.angle / 2.0).cos(), (self.angle / 2.0).sin());
+        [[Comp(c, 0.0), Comp(-s, 0.0)], [Comp(s, 0.0), Comp(c, 0.0)]]
+    }
+}
+
+impl Applicable for RY {
+    fn name(&self) -> String {
+        format!("RY_{}({})", self.angle, self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::RY,
+            vec![self.target_bit],
+            vec![],
+            vec![self.angle],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_matrix(qubits, iter, self.target_bit, &self.matrix())
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, self.matrix(), controls, &[])
+    }
+}
+
+impl Inversible for RY {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(RY::new(self.target_bit, -self.angle))
+    }
+}
+impl Operator for RY {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(RY::new(self.target_bit, self.angle * k as f64)))
+    }
+
+    remap_target_bit!();
+}
+
+/**
+Rotation around the z-axis. exp(-iθZ/2) = exp(-iθ/2)|0⟩⟨0| + exp(iθ/2)|1⟩⟨1|
+
+Unlike [`R`], both basis states receive a phase, so RZ(θ) = exp(-iθ/2)R(θ).
+
+# Usage
+```
+use Qit::{gates::RZ, core::{Applicable, Comp, Qubits}};
+
+// RZ(θ)|1⟩ = exp(iθ/2)|1⟩
+let q_out = RZ::new(0, 0.3).apply(Qubits::from_num(1, 1));
+assert!((q_out.bits[1] - Comp::new(0.15_f64.cos(), 0.15_f64.sin())).abs_square() < 1e-12);
+```
+ */
+#[derive(Clone, Copy)]
+pub struct RZ {
+    target_bit: usize,
+    angle: f64,
+}
+
+impl RZ {
+    pub fn new(target_bit: usize, angle: f64) -> Self {
+        RZ { target_bit, angle }
+    }
+
+    fn matrix(&self) -> Matrix2 {
+        let (c, s) = ((self.angle / 2.0).cos(), (self.angle / 2.0).sin());
+        [[Comp(c, -s), ZERO], [ZERO, Comp(c, s)]]
+    }
+}
+
+impl Applicable for RZ {
+    fn name(&self) -> String {
+        format!("RZ_{}({})", self.angle, self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::RZ,
+            vec![self.target_bit],
+            vec![],
+            vec![self.angle],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_matrix(qubits, iter, self.target_bit, &self.matrix())
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, self.matrix(), controls, &[])
+    }
+}
+
+impl Inversible for RZ {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(RZ::new(self.target_bit, -self.angle))
+    }
+}
+impl Operator for RZ {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(RZ::new(self.target_bit, self.angle * k as f64)))
+    }
+
+    remap_target_bit!();
+}
+
+/**
+General single-qubit gate U3(θ, φ, λ).
+
+|0⟩⟨0|cos(θ/2) - exp(iλ)|0⟩⟨1|sin(θ/2) + exp(iφ)|1⟩⟨0|sin(θ/2) + exp(i(φ+λ))|1⟩⟨1|cos(θ/2)
+
+The inverse is U3(-θ, -λ, -φ).
+
+# Usage
+```
+use Qit::{gates::{U3, H}, core::{Applicable, Qubits}};
+use std::f64::consts::PI;
+
+// U3(π/2, 0, π) = H
+let q_u3 = U3::new(0, PI / 2.0, 0.0, PI).apply(Qubits::from_num(1, 1));
+let q_h = H::new(0).apply(Qubits::from_num(1, 1));
+assert!((q_u3.bits[1] - q_h.bits[1]).abs_square() < 1e-12);
+```
+ */
+#[derive(Clone, Copy)]
+pub struct U3 {
+    target_bit: usize,
+    theta: f64,
+    phi: f64,
+    lambda: f64,
+}
+
+impl U3 {
+    pub fn new(target_bit: usize, theta: f64, phi: f64, lambda: f64) -> Self {
+        U3 {
+            target_bit,
+            theta,
+            phi,
+            lambda,
+        }
+    }
+
+    fn matrix(&self) -> Matrix2 {
+        let (c, s) = ((self.theta / 2.0).cos(), (self.theta / 2.0).sin());
+        let e = |angle: f64| Comp(angle.cos(), angle.sin());
+        [
+            [Comp(c, 0.0), e(self.lambda) * -s],
+            [e(self.phi) * s, e(self.phi + self.lambda) * c],
+        ]
+    }
+}
+
+impl Applicable for U3 {
+    fn name(&self) -> String {
+        format!(
+            "U3_({},{},{})({})",
+            self.theta, self.phi, self.lambda, self.target_bit
+        )
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::U3,
+            vec![self.target_bit],
+            vec![],
+            vec![self.theta, self.phi, self.lambda],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        apply_matrix(qubits, iter, self.target_bit, &self.matrix())
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(self, sink, self.target_bit, self.matrix(), controls, &[])
+    }
+}
+
+impl Inversible for U3 {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(U3::new(
+            self.target_bit,
+            -self.theta,
+            -self.lambda,
+            -self.phi,
+        ))
+    }
+}
+impl Operator for U3 {
+    /**
+    A power of U3 is in general not a U3 (its top-left entry is complex), so it is returned as the
+    2x2 [`MatrixGate`] raised to the k-th power.
+     */
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let matrix = self.matrix().iter().map(|row| row.to_vec()).collect();
+        let label = format!("U3_({},{},{})", self.theta, self.phi, self.lambda);
+        MatrixGate::new(vec![self.target_bit], matrix, label).power(k)
+    }
+
+    remap_target_bit!();
+}
+
+/**
+Controlled-Not Gate.
+
+# Usage
+
+```
+use Qit::core::{Applicable, Qubits, Comp};
+use Qit::gates::CX;
+
+// create CX(0 → 1)
+let cx01 = CX::new(0, 1);
+let q_in = Qubits::from_num(2, 1);
+let q_out = cx01.apply(q_in);
+q_out.print_cmps();
+// |00⟩ : +0.000 +0.000i
+// |01⟩ : +0.000 +0.000i
+// |10⟩ : +0.000 +0.000i
+// |11⟩ : +1.000 +0.000i
+assert_eq!(q_out.bits[0b11], Comp::new(1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct CX {
+    controll_bit: usize,
+    target_bit: usize,
+    polarity: bool,
+}
+
+impl CX {
+    pub fn new(controll_bit: usize, target_bit: usize) -> Self {
+        CX::new_polarized(controll_bit, true, target_bit)
+    }
+
+    /**
+    CX whose control fires on |1⟩ if `polarity` is true and on |0⟩ otherwise.
+     */
+    pub fn new_polarized(controll_bit: usize, polarity: bool, target_bit: usize) -> Self {
+        CX {
+            controll_bit,
+            target_bit,
+            polarity,
+        }
+    }
+}
+
+impl Applicable for CX {
+    fn name(&self) -> String {
+        format!(
+            "CX({}->{})",
+            control_label(self.controll_bit, self.polarity),
+            self.target_bit
+        )
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(
+            GateKind::CX,
+            vec![self.target_bit],
+            vec![self.controll_bit],
+            vec![],
+        );
+        info.polarities = vec![self.polarity];
+        info
+    }
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let step = 1 << self.target_bit;
+        let (mask, value) = control_pattern(&[self.controll_bit], &[self.polarity]);
+        let iter = iter.merge_with(mask | step, value | step);
+
+        for idx1 in iter {
+            let idx0 = idx1 - step;
+            qubits.bits.swap(idx0, idx1);
+        }
+
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (bits, polarities) = ([self.controll_bit], [self.polarity]);
+        flip_negative_controls(self, sink, &bits, &polarities)?;
+        lower_matrix(self, sink, self.target_bit, X_MATRIX, controls, &bits)?;
+        flip_negative_controls(self, sink, &bits, &polarities)
+    }
+}
+
+impl Inversible for CX {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for CX {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(&[self.controll_bit, self.target_bit], mapping);
+        let mut gate = *self;
+        gate.controll_bit = bits[0];
+        gate.target_bit = bits[1];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Controlled-Z Gate. Multiplies |11⟩ by -1.
+
+CZ is symmetric, so it makes no difference which bit is called the control.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::CZ;
+
+let q_out = CZ::new(0, 1).apply(Qubits::from_num(2, 0b11));
+assert_eq!(q_out.bits[0b11], Comp::new(-1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct CZ {
+    controll_bit: usize,
+    target_bit: usize,
+}
+
+impl CZ {
+    pub fn new(controll_bit: usize, target_bit: usize) -> Self {
+        assert_ne!(controll_bit, target_bit);
+        CZ {
+            controll_bit,
+            target_bit,
+        }
+    }
+}
+
+impl Applicable for CZ {
+    fn name(&self) -> String {
+        format!("CZ({}->{})", self.controll_bit, self.target_bit)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::CZ,
+            vec![self.target_bit],
+            vec![self.controll_bit],
+            vec![],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let iter = iter.merge(1 << self.controll_bit);
+        apply_phase(qubits, &iter, self.target_bit, Comp(-1.0, 0.0))
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(
+            self,
+            sink,
+            self.target_bit,
+            Z_MATRIX,
+            controls,
+            &[self.controll_bit],
+        )
+    }
+}
+
+impl Inversible for CZ {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for CZ {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(&[self.controll_bit, self.target_bit], mapping);
+        let mut gate = *self;
+        gate.controll_bit = bits[0];
+        gate.target_bit = bits[1];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Controlled phase shift Gate. Multiplies |11⟩ by exp(i angle); the controlled version of [`R`].
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::CPhase;
+use std::f64::consts::PI;
+
+let cp = CPhase::new(0, 1, 0.5 * PI);
+let q_out = cp.apply(Qubits::from_num(2, 0b11));
+assert!((q_out.bits[0b11] - Comp::new(0.0, 1.0)).abs_square() < 1e-20);
+// nothing happens unless both bits are |1⟩
+let q_out = cp.apply(Qubits::from_num(2, 0b10));
+assert_eq!(q_out.bits[0b10], Comp::new(1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct CPhase {
+    controll_bit: usize,
+    target_bit: usize,
+    angle: f64,
+    phase: Comp,
+}
+
+impl CPhase {
+    pub fn new(controll_bit: usize, target_bit: usize, angle: f64) -> Self {
+        assert_ne!(controll_bit, target_bit);
+        CPhase {
+            controll_bit,
+            target_bit,
+            angle,
+            phase: Comp(angle.cos(), angle.sin()),
+        }
+    }
+}
+
+impl Applicable for CPhase {
+    fn name(&self) -> String {
+        format!(
+            "CPhase_{}({}->{})",
+            self.angle, self.controll_bit, self.target_bit
+        )
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::CPhase,
+            vec![self.target_bit],
+            vec![self.controll_bit],
+            vec![self.angle],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let iter = iter.merge(1 << self.controll_bit);
+        apply_phase(qubits, &iter, self.target_bit, self.phase)
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        lower_matrix(
+            self,
+            sink,
+            self.target_bit,
+            [[ONE, ZERO], [ZERO, self.phase]],
+            controls,
+            &[self.controll_bit],
+        )
+    }
+}
+
+impl Inversible for CPhase {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(CPhase::new(
+            self.controll_bit,
+            self.target_bit,
+            2.0 * PI - self.angle,
+        ))
+    }
+}
+impl Operator for CPhase {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let angle = self.angle * k as f64;
+        Some(Box::new(CPhase::new(
+            self.controll_bit,
+            self.target_bit,
+            angle,
+        )))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(&[self.controll_bit, self.target_bit], mapping);
+        let mut gate = *self;
+        gate.controll_bit = bits[0];
+        gate.target_bit = bits[1];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Controlled-Controlled-Not(CXX) Gate.
+
+# Usage
+
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::CCX;
+
+let ccx = CCX::new(0, 1, 2);
+// q_in = |011⟩
+let q_in = Qubits::from_num(3, 3);
+let q_out = ccx.apply(q_in);
+q_out.print_cmps();
+// |000⟩ : +0.000 +0.000i
+// |001⟩ : +0.000 +0.000i
+// |010⟩ : +0.000 +0.000i
+// |011⟩ : +0.000 +0.000i
+// |100⟩ : +0.000 +0.000i
+// |101⟩ : +0.000 +0.000i
+// |110⟩ : +0.000 +0.000i
+// |111⟩ : +1.000 +0.000i
+assert_eq!(q_out.bits[0b111], Comp::new(1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct CCX {
+    controll_bit1: usize,
+    controll_bit2: usize,
+    target_bit: usize,
+    polarities: [bool; 2],
+}
+
+impl CCX {
+    pub fn new(controll_bit1: usize, controll_bit2: usize, target_bit: usize) -> Self {
+        CCX::new_polarized(controll_bit1, true, controll_bit2, true, target_bit)
+    }
+
+    /**
+    CCX whose controls fire on |1⟩ if their polarity is true and on |0⟩ otherwise.
+     */
+    pub fn new_polarized(
+        controll_bit1: usize,
+        polarity1: bool,
+        controll_bit2: usize,
+        polarity2: bool,
+        target_bit: usize,
+    ) -> Self {
+        CCX {
+            controll_bit1,
+            controll_bit2,
+            target_bit,
+            polarities: [polarity1, polarity2],
+        }
+    }
+}
+
+impl Applicable for CCX {
+    fn name(&self) -> String {
+        format!(
+            "CCX([{},{}]->{})",
+            control_label(self.controll_bit1, self.polarities[0]),
+            control_label(self.controll_bit2, self.polarities[1]),
+            self.target_bit
+        )
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(
+            GateKind::CCX,
+            vec![self.target_bit],
+            vec![self.controll_bit1, self.controll_bit2],
+            vec![],
+        );
+        info.polarities = self.polarities.to_vec();
+        info
+    }
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let step = 1 << self.target_bit;
+        let (mask, value) =
+            control_pattern(&[self.controll_bit1, self.controll_bit2], &self.polarities);
+        let iter = iter.merge_with(mask | step, value | step);
+
+        for idx1 in iter {
+            let idx0 = idx1 - step;
+            qubits.bits.swap(idx0, idx1);
+        }
+
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let bits = [self.controll_bit1, self.controll_bit2];
+        flip_negative_controls(self, sink, &bits, &self.polarities)?;
+        lower_matrix(self, sink, self.target_bit, X_MATRIX, controls, &bits)?;
+        flip_negative_controls(self, sink, &bits, &self.polarities)
+    }
+}
+
+impl Inversible for CCX {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for CCX {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(
+            &[self.controll_bit1, self.controll_bit2, self.target_bit],
+            mapping,
+        );
+        let mut gate = *self;
+        gate.controll_bit1 = bits[0];
+        gate.controll_bit2 = bits[1];
+        gate.target_bit = bits[2];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Controlled-Controlled-Controlled-...(N)-Not Gate
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::CNX;
+
+let cnx = CNX::new(vec![0, 1, 2], 3);
+// q_in = |0111⟩
+let q_in = Qubits::from_num(4, 7);
+let q_out = cnx.apply(q_in);
+q_out.print_cmps();
+// |0000⟩ : +0.000 +0.000i
+// |0001⟩ : +0.000 +0.000i
+//        .
+//        .
+//        .
+// |1110⟩ : +0.000 +0.000i
+// |1111⟩ : +1.000 +0.000i
+assert_eq!(q_out.bits[0b1111], Comp::new(1.0, 0.0));
+```
+*/
+#[derive(Clone)]
+pub struct CNX {
+    controll_bits: Vec<usize>,
+    target_bit: usize,
+    polarities: Vec<bool>,
+}
+
+impl CNX {
+    pub fn new(controll_bits: Vec<usize>, target_bit: usize) -> Self {
+        let polarities = vec![true; controll_bits.len()];
+        CNX::new_polarized(controll_bits, polarities, target_bit)
+    }
+
+    /**
+    CNX whose i-th control fires on |1⟩ if `polarities[i]` is true and on |0⟩ otherwise.
+     */
+    pub fn new_polarized(
+        controll_bits: Vec<usize>,
+        polarities: Vec<bool>,
+        target_bit: usize,
+    ) -> Self {
+        assert_eq!(controll_bits.len(), polarities.len());
+        CNX {
+            controll_bits,
+            target_bit,
+            polarities,
+        }
+    }
+}
+
+impl Applicable for CNX {
+    fn name(&self) -> String {
+        let mut s = String::from("CNX[");
+        for (i, p) in self.controll_bits.iter().zip(self.polarities.iter()) {
+            s += &format!("{},", control_label(*i, *p));
+        }
+        s += &format!("]->{}", self.target_bit);
+        s
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(
+            GateKind::CNX,
+            vec![self.target_bit],
+            self.controll_bits.clone(),
+            vec![],
+        );
+        info.polarities = self.polarities.clone();
+        info
+    }
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
         let step = 1 << self.target_bit;
-        let iter = iter.merge(self.cbit_mask() | step);
+        let (mask, value) = control_pattern(&self.controll_bits, &self.polarities);
+        let iter = iter.merge_with(mask | step, value | step);
+
+        for idx1 in iter {
+            let idx0 = idx1 - step;
+            qubits.bits.swap(idx0, idx1);
+        }
+
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        flip_negative_controls(self, sink, &self.controll_bits, &self.polarities)?;
+        lower_matrix(
+            self,
+            sink,
+            self.target_bit,
+            X_MATRIX,
+            controls,
+            &self.controll_bits,
+        )?;
+        flip_negative_controls(self, sink, &self.controll_bits, &self.polarities)
+    }
+}
+
+impl Inversible for CNX {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for CNX {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let mut bits = self.controll_bits.clone();
+        bits.push(self.target_bit);
+        let mut bits = remap_bits(&bits, mapping);
+        let mut gate = self.clone();
+        gate.target_bit = bits.pop().unwrap();
+        gate.controll_bits = bits;
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Multi-Controlled Z Gate. Multiplies the state by -1 when every listed bit is |1⟩.
+
+The gate is symmetric in its bits. Like [`CZ`], `describe` reports the last bit as the target and
+the others as controls, which is also how it is lowered.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::MCZ;
+
+let mcz = MCZ::new(vec![0, 1, 2]);
+let q_out = mcz.apply(Qubits::from_num(3, 0b111));
+assert_eq!(q_out.bits[0b111], Comp::new(-1.0, 0.0));
+let q_out = mcz.apply(Qubits::from_num(3, 0b011));
+assert_eq!(q_out.bits[0b011], Comp::new(1.0, 0.0));
+```
+*/
+#[derive(Clone)]
+pub struct MCZ {
+    controll_bits: Vec<usize>,
+}
+
+impl MCZ {
+    pub fn new(controll_bits: Vec<usize>) -> Self {
+        assert!(!controll_bits.is_empty());
+        MCZ { controll_bits }
+    }
+}
+
+impl Applicable for MCZ {
+    fn name(&self) -> String {
+        format!("MCZ({:?})", self.controll_bits)
+    }
+
+    fn describe(&self) -> GateInfo {
+        let (target, controls) = self.controll_bits.split_last().unwrap();
+        GateInfo::new(GateKind::MCZ, vec![*target], controls.to_vec(), vec![])
+    }
+
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let mut mask = 0;
+        for c in self.controll_bits.iter() {
+            mask |= 1 << c;
+        }
+        for idx in iter.merge(mask) {
+            qubits.bits[idx] = qubits.bits[idx] * Comp(-1.0, 0.0);
+        }
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (target, own_controls) = self.controll_bits.split_last().unwrap();
+        lower_matrix(self, sink, *target, Z_MATRIX, controls, own_controls)
+    }
+}
+
+impl Inversible for MCZ {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for MCZ {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let mut gate = self.clone();
+        gate.controll_bits = remap_bits(&self.controll_bits, mapping);
+        Some(Box::new(gate))
+    }
+}
+
+/**
+SWAP Gate. Exchanges the states of two qubits.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::SWAP;
+
+// |01⟩ → |10⟩
+let q_out = SWAP::new(0, 1).apply(Qubits::from_num(2, 0b01));
+assert_eq!(q_out.bits[0b10], Comp::new(1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct SWAP {
+    target_bit1: usize,
+    target_bit2: usize,
+}
+
+impl SWAP {
+    pub fn new(target_bit1: usize, target_bit2: usize) -> Self {
+        assert_ne!(target_bit1, target_bit2);
+        SWAP {
+            target_bit1,
+            target_bit2,
+        }
+    }
+}
+
+impl Applicable for SWAP {
+    fn name(&self) -> String {
+        format!("SWAP({},{})", self.target_bit1, self.target_bit2)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::SWAP,
+            vec![self.target_bit1, self.target_bit2],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        swap_amplitudes(qubits, iter, self.target_bit1, self.target_bit2, ONE)
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (a, b) = (self.target_bit1, self.target_bit2);
+        lower_matrix(self, sink, b, X_MATRIX, controls, &[a])?;
+        lower_matrix(self, sink, a, X_MATRIX, controls, &[b])?;
+        lower_matrix(self, sink, b, X_MATRIX, controls, &[a])
+    }
+}
+
+impl Inversible for SWAP {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for SWAP {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(&[self.target_bit1, self.target_bit2], mapping);
+        let mut gate = *self;
+        gate.target_bit1 = bits[0];
+        gate.target_bit2 = bits[1];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+iSWAP Gate. Exchanges the states of two qubits, multiplying |01⟩ and |10⟩ by i.
+
+Calling `inverse` turns it into iSWAP†, which multiplies them by -i.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::ISWAP;
+
+// |01⟩ → i|10⟩
+let q_out = ISWAP::new(0, 1).apply(Qubits::from_num(2, 0b01));
+assert_eq!(q_out.bits[0b10], Comp::new(0.0, 1.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct ISWAP {
+    target_bit1: usize,
+    target_bit2: usize,
+    inversed: bool,
+}
+
+impl ISWAP {
+    pub fn new(target_bit1: usize, target_bit2: usize) -> Self {
+        assert_ne!(target_bit1, target_bit2);
+        ISWAP {
+            target_bit1,
+            target_bit2,
+            inversed: false,
+        }
+    }
+
+    fn phase(&self) -> Comp {
+        if self.inversed {
+            Comp(0.0, -1.0)
+        } else {
+            Comp(0.0, 1.0)
+        }
+    }
+}
+
+impl Applicable for ISWAP {
+    fn name(&self) -> String {
+        let label = if self.inversed { "ISWAPdg" } else { "ISWAP" };
+        format!("{}({},{})", label, self.target_bit1, self.target_bit2)
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            if self.inversed {
+                GateKind::ISWAPdg
+            } else {
+                GateKind::ISWAP
+            },
+            vec![self.target_bit1, self.target_bit2],
+            vec![],
+            vec![],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        swap_amplitudes(
+            qubits,
+            iter,
+            self.target_bit1,
+            self.target_bit2,
+            self.phase(),
+        )
+    }
+
+    /**
+    iSWAP = SWAP・CZ・(S⊗S)
+     */
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (a, b) = (self.target_bit1, self.target_bit2);
+        let s = [[ONE, ZERO], [ZERO, self.phase()]];
+        lower_matrix(self, sink, a, s, controls, &[])?;
+        lower_matrix(self, sink, b, s, controls, &[])?;
+        lower_matrix(self, sink, b, Z_MATRIX, controls, &[a])?;
+        SWAP::new(a, b).lower(sink, controls)
+    }
+}
+
+impl Inversible for ISWAP {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(ISWAP {
+            inversed: !self.inversed,
+            ..*self
+        })
+    }
+}
+impl Operator for ISWAP {
+    /**
+    ISWAP^2 = ISWAP†^2 = Z⊗Z and ISWAP^4 = I.
+     */
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let zz = U::new(
+            vec![
+                Box::new(Z::new(self.target_bit1)),
+                Box::new(Z::new(self.target_bit2)),
+            ],
+            format!("{}^2", self.name()),
+        );
+        period4_power(self, Box::new(zz), k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(&[self.target_bit1, self.target_bit2], mapping);
+        let mut gate = *self;
+        gate.target_bit1 = bits[0];
+        gate.target_bit2 = bits[1];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Controlled-SWAP (Fredkin) Gate. Exchanges two qubits when the control bit is |1⟩.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::CSWAP;
+
+let cswap = CSWAP::new(2, 0, 1);
+// |101⟩ → |110⟩
+let q_out = cswap.apply(Qubits::from_num(3, 0b101));
+assert_eq!(q_out.bits[0b110], Comp::new(1.0, 0.0));
+// |001⟩ is unchanged
+let q_out = cswap.apply(Qubits::from_num(3, 0b001));
+assert_eq!(q_out.bits[0b001], Comp::new(1.0, 0.0));
+```
+ */
+#[derive(Clone, Copy)]
+pub struct CSWAP {
+    controll_bit: usize,
+    target_bit1: usize,
+    target_bit2: usize,
+}
+
+impl CSWAP {
+    pub fn new(controll_bit: usize, target_bit1: usize, target_bit2: usize) -> Self {
+        assert_ne!(target_bit1, target_bit2);
+        CSWAP {
+            controll_bit,
+            target_bit1,
+            target_bit2,
+        }
+    }
+}
+
+impl Applicable for CSWAP {
+    fn name(&self) -> String {
+        format!(
+            "CSWAP({}->{},{})",
+            self.controll_bit, self.target_bit1, self.target_bit2
+        )
+    }
+
+    fn describe(&self) -> GateInfo {
+        GateInfo::new(
+            GateKind::CSWAP,
+            vec![self.target_bit1, self.target_bit2],
+            vec![self.controll_bit],
+            vec![],
+        )
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        let iter = iter.merge(1 << self.controll_bit);
+        swap_amplitudes(qubits, &iter, self.target_bit1, self.target_bit2, ONE)
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (c, a, b) = (self.controll_bit, self.target_bit1, self.target_bit2);
+        lower_matrix(self, sink, a, X_MATRIX, controls, &[b])?;
+        lower_matrix(self, sink, b, X_MATRIX, controls, &[c, a])?;
+        lower_matrix(self, sink, a, X_MATRIX, controls, &[b])
+    }
+}
+
+impl Inversible for CSWAP {
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+impl Operator for CSWAP {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        involution_power(self, k)
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let bits = remap_bits(
+            &[self.controll_bit, self.target_bit1, self.target_bit2],
+            mapping,
+        );
+        let mut gate = *self;
+        gate.controll_bit = bits[0];
+        gate.target_bit1 = bits[1];
+        gate.target_bit2 = bits[2];
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Gate given by an explicit unitary matrix on one or two qubits.
+
+`matrix` is 2x2 for one target and 4x4 for two targets. Bit j of the row and column index
+corresponds to `targets[j]`, so for targets `[a, b]` the index is `a_bit + 2 * b_bit`.
+The matrix must be unitary; `inverse` replaces it with its conjugate transpose.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::{MatrixGate, CU};
+
+let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+let i = Comp::new(0.0, 1.0);
+// iSWAP on qubits 0 and 1
+let iswap = MatrixGate::new(
+    vec![0, 1],
+    vec![
+        vec![l, o, o, o],
+        vec![o, o, i, o],
+        vec![o, i, o, o],
+        vec![o, o, o, l],
+    ],
+    String::from("iswap"),
+);
+let q_out = iswap.apply(Qubits::from_num(3, 0b001));
+assert_eq!(q_out.bits[0b010], i);
+
+// controlled by qubit 2
+let c_iswap = CU::new(2, vec![Box::new(iswap)], String::from("c-iswap"));
+let q_out = c_iswap.apply(Qubits::from_num(3, 0b001));
+assert_eq!(q_out.bits[0b001], l);
+```
+ */
+#[derive(Clone)]
+pub struct MatrixGate {
+    targets: Vec<usize>,
+    matrix: Vec<Vec<Comp>>,
+    label: String,
+}
+
+impl MatrixGate {
+    pub fn new(targets: Vec<usize>, matrix: Vec<Vec<Comp>>, label: String) -> Self {
+        assert!(
+            targets.len() == 1 || targets.len() == 2,
+            "MatrixGate acts on one or two qubits"
+        );
+        if targets.len() == 2 {
+            assert_ne!(targets[0], targets[1]);
+        }
+        let dim = 1 << targets.len();
+        assert_eq!(matrix.len(), dim, "matrix must be {}x{}", dim, dim);
+        for row in matrix.iter() {
+            assert_eq!(row.len(), dim, "matrix must be {}x{}", dim, dim);
+        }
+        assert!(is_unitary(&matrix), "matrix is not unitary");
+        MatrixGate {
+            targets,
+            matrix,
+            label,
+        }
+    }
+}
+
+impl Applicable for MatrixGate {
+    fn name(&self) -> String {
+        let targets: Vec<String> = self.targets.iter().map(|t| t.to_string()).collect();
+        format!("M[{}]({})", self.label, targets.join(","))
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::Matrix, self.targets.clone(), vec![], vec![]);
+        info.label = self.label.clone();
+        info.matrix = Some(self.matrix.clone());
+        info
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        if self.targets.len() == 1 {
+            let m = [
+                [self.matrix[0][0], self.matrix[0][1]],
+                [self.matrix[1][0], self.matrix[1][1]],
+            ];
+            return apply_matrix(qubits, iter, self.targets[0], &m);
+        }
+
+        let mut qubits = qubits;
+        let (step0, step1) = (1 << self.targets[0], 1 << self.targets[1]);
+        let iter = iter.merge(step0 | step1);
+        for idx11 in iter {
+            let base = idx11 - step0 - step1;
+            let idx = [base, base + step0, base + step1, idx11];
+            let amps = idx.map(|i| qubits.bits[i]);
+            for (row, i) in idx.iter().enumerate() {
+                let mut sum = Comp::zero();
+                for (m, a) in self.matrix[row].iter().zip(amps.iter()) {
+                    sum = sum + *m * *a;
+                }
+                qubits.bits[*i] = sum;
+            }
+        }
+        qubits
+    }
 
-        for idx1 in iter {
-            let idx0 = idx1 - step;
-            let temp = qubits.bits[idx0];
-            qubits.bits[idx0] = qubits.bits[idx1];
-            qubits.bits[idx1] = temp;
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        if self.targets.len() != 1 {
+            return Err(UnsupportedGate::new(
+                self.name(),
+                "two-qubit matrices cannot be lowered to controlled single-qubit matrices",
+            ));
+        }
+        let m = [
+            [self.matrix[0][0], self.matrix[0][1]],
+            [self.matrix[1][0], self.matrix[1][1]],
+        ];
+        lower_matrix(self, sink, self.targets[0], m, controls, &[])
+    }
+}
+
+impl Inversible for MatrixGate {
+    fn dagger(&self) -> Box<dyn Operator> {
+        let dim = self.matrix.len();
+        let matrix = (0..dim)
+            .map(|i| {
+                (0..dim)
+                    .map(|j| Comp(self.matrix[j][i].0, -self.matrix[j][i].1))
+                    .collect()
+            })
+            .collect();
+        Box::new(MatrixGate {
+            matrix,
+            ..self.clone()
+        })
+    }
+}
+impl Operator for MatrixGate {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let dim = self.matrix.len();
+        let mut result: Vec<Vec<Comp>> = (0..dim)
+            .map(|i| (0..dim).map(|j| if i == j { ONE } else { ZERO }).collect())
+            .collect();
+        let mut base = self.matrix.clone();
+        let mut e = k;
+        while e > 0 {
+            if e & 1 == 1 {
+                result = matmul(&result, &base);
+            }
+            base = matmul(&base, &base);
+            e >>= 1;
+        }
+        let label = format!("{}^{}", self.label, k);
+        Some(Box::new(MatrixGate {
+            targets: self.targets.clone(),
+            matrix: result,
+            label,
+        }))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let mut gate = self.clone();
+        gate.targets = remap_bits(&self.targets, mapping);
+        Some(Box::new(gate))
+    }
+}
+
+/**
+Rotation generated by a Pauli string. exp(-iθP/2) = cos(θ/2)I - i sin(θ/2)P
+
+The rotation is applied directly to the amplitudes: every pair |j⟩, |j ^ flip⟩ of basis states
+connected by P is mixed once. [`PauliRotation::decompose`] gives the equivalent circuit of
+basis changes, a CX ladder and RZ, which is also what `lower` feeds to other simulators.
+The Pauli matrices follow the convention of the pauli module.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::{PauliRotation, RX};
+use Qit::pauli::{Pauli, PauliString};
+use std::f64::consts::PI;
+
+// on one qubit, exp(-iθX/2) is RX(θ)
+let rot = PauliRotation::new(PauliString::new(vec![(0, Pauli::X)]), 0.3);
+let q_in = Qubits::zeros(1);
+let (a, b) = (rot.apply(q_in.clone()), RX::new(0, 0.3).apply(q_in));
+assert!((a.bits[1] - b.bits[1]).abs_square() < 1e-20);
+
+// exp(-iπ/2 X0 X1)|00⟩ = -i|11⟩
+let rot = PauliRotation::new(PauliString::new(vec![(0, Pauli::X), (1, Pauli::X)]), PI);
+let q_out = rot.apply(Qubits::zeros(2));
+assert!((q_out.bits[0b11] - Comp::new(0.0, -1.0)).abs_square() < 1e-20);
+```
+ */
+#[derive(Clone)]
+pub struct PauliRotation {
+    paulis: PauliString,
+    angle: f64,
+}
+
+impl PauliRotation {
+    pub fn new(paulis: PauliString, angle: f64) -> Self {
+        assert!(
+            paulis.paulis.iter().any(|(_, p)| *p != Pauli::I),
+            "Pauli string has no X, Y or Z"
+        );
+        PauliRotation { paulis, angle }
+    }
+
+    /**
+    The same rotation as a circuit of H, S, Sdg, CX and RZ.
+     */
+    pub fn decompose(&self) -> U {
+        let active: Vec<(usize, Pauli)> = self
+            .paulis
+            .paulis
+            .iter()
+            .filter(|(_, p)| *p != Pauli::I)
+            .cloned()
+            .collect();
+        // basis change mapping every Pauli to Z
+        let mut basis: OperatorVec = Vec::new();
+        for (q, p) in active.iter() {
+            match p {
+                Pauli::X => basis.push(Box::new(H::new(*q))),
+                Pauli::Y => {
+                    basis.push(Box::new(PhaseGate::sdg(*q)));
+                    basis.push(Box::new(H::new(*q)));
+                }
+                _ => {}
+            }
+        }
+        // parity of the active qubits on the last one
+        let mut ladder: OperatorVec = Vec::new();
+        for i in 1..active.len() {
+            ladder.push(Box::new(CX::new(active[i - 1].0, active[i].0)));
+        }
+        let last = active[active.len() - 1].0;
+
+        let mut gates: OperatorVec = basis.clone();
+        gates.extend(ladder.clone());
+        gates.push(Box::new(RZ::new(last, self.angle)));
+        gates.extend(U::new(ladder, String::new()).adjoint().gates);
+        gates.extend(U::new(basis, String::new()).adjoint().gates);
+        U::new(gates, self.name())
+    }
+}
+
+impl Applicable for PauliRotation {
+    fn name(&self) -> String {
+        format!("PauliRotation_{}({})", self.angle, self.paulis)
+    }
+
+    fn describe(&self) -> GateInfo {
+        let targets = self
+            .paulis
+            .paulis
+            .iter()
+            .filter(|(_, p)| *p != Pauli::I)
+            .map(|(q, _)| *q)
+            .collect();
+        let mut info = GateInfo::new(GateKind::PauliRotation, targets, vec![], vec![self.angle]);
+        info.label = format!("{}", self.paulis);
+        info
+    }
+
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        // identity factors do not act, so they may be controls of an enclosing gate
+        for (q, p) in self.paulis.paulis.iter() {
+            assert!(
+                *p == Pauli::I || iter.mask & (1 << q) == 0,
+                "invalid mask was input."
+            );
+        }
+        let (c, s) = ((0.5 * self.angle).cos(), (0.5 * self.angle).sin());
+        let minus_is = Comp(0.0, -s);
+        let flip = self.paulis.flip_mask();
+        if flip == 0 {
+            // diagonal: |j⟩ → (c - is·phase(j))|j⟩
+            for idx in iter.merge(0) {
+                let factor = minus_is * self.paulis.phase(idx) + c;
+                qubits.bits[idx] = qubits.bits[idx] * factor;
+            }
+            return qubits;
+        }
+        // each pair (j, j ^ flip) once, from the member whose highest flipped bit is 0
+        let top = 1 << (usize::BITS - 1 - flip.leading_zeros());
+        for idx0 in iter.merge_with(top, 0) {
+            let idx1 = idx0 ^ flip;
+            let (a0, a1) = (qubits.bits[idx0], qubits.bits[idx1]);
+            qubits.bits[idx0] = a0 * c + minus_is * self.paulis.phase(idx1) * a1;
+            qubits.bits[idx1] = a1 * c + minus_is * self.paulis.phase(idx0) * a0;
+        }
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        self.decompose().lower(sink, controls)
+    }
+}
+
+impl Inversible for PauliRotation {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(PauliRotation {
+            angle: -self.angle,
+            ..self.clone()
+        })
+    }
+}
+impl Operator for PauliRotation {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let angle = self.angle * k as f64;
+        Some(Box::new(PauliRotation::new(self.paulis.clone(), angle)))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let paulis = self
+            .paulis
+            .paulis
+            .iter()
+            .map(|(q, p)| (mapping(*q), *p))
+            .collect();
+        Some(Box::new(PauliRotation::new(
+            PauliString::new(paulis),
+            self.angle,
+        )))
+    }
+}
+
+/**
+Diagonal oracle |x⟩ → exp(iφ(x))|x⟩ on a register.
+
+x is the value of the register, whose j-th qubit is bit j. The phases are applied in a single pass
+over the state vector, and the oracle can be placed inside U and CU. `inverse` negates every phase.
+The phase function must be `Send + Sync`, so the oracle can be shared between threads.
+
+`lower` has no structure to exploit: it emits one multi-controlled diagonal gate for each of the
+2^(n-1) values of the other n - 1 qubits of the register, skipping the ones with zero phase. Keep the
+register small when running on the sparse, stabilizer or MPS backends.
+
+# Usage
+```
+use Qit::core::{Applicable, Comp, Qubits};
+use Qit::gates::{PhaseOracle, H, U};
+
+// (-1)^f(x) with f(x) = (x == 5) on qubits 0..3
+let mut gates: Vec<Box<dyn Qit::core::Operator>> = (0..3).map(|i| Box::new(H::new(i)) as _).collect();
+gates.push(Box::new(PhaseOracle::from_bool(vec![0, 1, 2], |x| x == 5, String::from("x==5"))));
+let q_out = U::new(gates, String::from("mark")).apply(Qubits::zeros(3));
+let amp = (0.125_f64).sqrt();
+assert!((q_out.bits[5] - Comp::new(-amp, 0.0)).abs_square() < 1e-20);
+assert!((q_out.bits[4] - Comp::new(amp, 0.0)).abs_square() < 1e-20);
+```
+ */
+#[derive(Clone)]
+pub struct PhaseOracle {
+    register: Vec<usize>,
+    phase: Arc<dyn Fn(usize) -> f64 + Send + Sync>,
+    inversed: bool,
+    label: String,
+}
+
+impl PhaseOracle {
+    pub fn new(
+        register: Vec<usize>,
+        phase: impl Fn(usize) -> f64 + Send + Sync + 'static,
+        label: String,
+    ) -> Self {
+        assert!(!register.is_empty());
+        PhaseOracle {
+            register,
+            phase: Arc::new(phase),
+            inversed: false,
+            label,
+        }
+    }
+
+    /**
+    |x⟩ → (-1)^f(x)|x⟩
+     */
+    pub fn from_bool(
+        register: Vec<usize>,
+        f: impl Fn(usize) -> bool + Send + Sync + 'static,
+        label: String,
+    ) -> Self {
+        PhaseOracle::new(register, move |x| if f(x) { PI } else { 0.0 }, label)
+    }
+
+    fn factor(&self, x: usize) -> Comp {
+        let phi = if self.inversed {
+            -(self.phase)(x)
+        } else {
+            (self.phase)(x)
+        };
+        Comp(phi.cos(), phi.sin())
+    }
+}
+
+impl Applicable for PhaseOracle {
+    fn name(&self) -> String {
+        let label = if self.inversed { "dg" } else { "" };
+        format!("PhaseOracle[{}]{}({:?})", self.label, label, self.register)
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::PhaseOracle, self.register.clone(), vec![], vec![]);
+        info.label = self.label.clone();
+        info
+    }
+
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        for q in self.register.iter() {
+            assert!(iter.mask & (1 << q) == 0, "invalid mask was input.");
+        }
+        for idx in iter.merge(0) {
+            let factor = self.factor(register_value(&self.register, idx));
+            qubits.bits[idx] = qubits.bits[idx] * factor;
+        }
+        qubits
+    }
+
+    /**
+    One diagonal matrix on the first qubit of the register for every value of the others,
+    which become (X-conjugated) controls: 2^(n-1) operations for an n-qubit register.
+     */
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        let (target, rest) = self.register.split_first().unwrap();
+        for r in 0..(1 << rest.len()) {
+            let (f0, f1) = (self.factor(r << 1), self.factor((r << 1) | 1));
+            if f0 == ONE && f1 == ONE {
+                continue;
+            }
+            let polarities: Vec<bool> = (0..rest.len()).map(|j| (r >> j) & 1 == 1).collect();
+            flip_negative_controls(self, sink, rest, &polarities)?;
+            lower_matrix(
+                self,
+                sink,
+                *target,
+                [[f0, ZERO], [ZERO, f1]],
+                controls,
+                rest,
+            )?;
+            flip_negative_controls(self, sink, rest, &polarities)?;
+        }
+        Ok(())
+    }
+}
+
+impl Inversible for PhaseOracle {
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(PhaseOracle {
+            inversed: !self.inversed,
+            ..self.clone()
+        })
+    }
+}
+impl Operator for PhaseOracle {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let mut gate = self.clone();
+        let phase = self.phase.clone();
+        gate.phase = Arc::new(move |x| k as f64 * phase(x));
+        gate.label = format!("{}^{}", self.label, k);
+        Some(Box::new(gate))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let mut gate = self.clone();
+        gate.register = remap_bits(&self.register, mapping);
+        Some(Box::new(gate))
+    }
+}
+
+#[derive(Clone)]
+enum PermutationForm {
+    // |x⟩ → |table[x]⟩ on register; inverse_table undoes it
+    Bijective {
+        register: Vec<usize>,
+        table: Arc<Vec<usize>>,
+        inverse_table: Arc<Vec<usize>>,
+    },
+    // |x⟩|y⟩ → |x⟩|y ^ table[x]⟩
+    Xor {
+        input: Vec<usize>,
+        output: Vec<usize>,
+        table: Arc<Vec<usize>>,
+    },
+}
+
+/**
+Oracle that permutes basis states according to a classical function.
+
+* [`PermutationOracle::bijective`]: |x⟩ → |f(x)⟩ on one register. f must be a bijection of 0..2^n.
+* [`PermutationOracle::xor`]: |x⟩|y⟩ → |x⟩|y ⊕ f(x)⟩ on an input and an output register. Any f works.
+
+x is the value of the register, whose j-th qubit is bit j. The function is evaluated once for every
+input value when the oracle is built, and the map is validated at that point.
+
+# Usage
+```
+use Qit::circuits::wrapping_qadd_const;
+use Qit::classical::run_classical;
+use Qit::gates::PermutationOracle;
+
+// a black-box version of wrapping_qadd_const
+let b = vec![0, 1, 2];
+let oracle = PermutationOracle::bijective(b.clone(), |x| (x + 3) % 8, String::from("+3"));
+for x in 0..8 {
+    let expected = run_classical(&wrapping_qadd_const(&b, 3), 3, x).unwrap();
+    assert_eq!(run_classical(&oracle, 3, x).unwrap(), expected);
+}
+
+// |x⟩|y⟩ → |x⟩|y ⊕ x²⟩ with x on qubits 0..2 and y on qubits 2..6
+let square = PermutationOracle::xor(vec![0, 1], vec![2, 3, 4, 5], |x| x * x, String::from("sq"));
+assert_eq!(run_classical(&square, 6, 0b0001_11).unwrap(), 0b1000_11);
+```
+ */
+#[derive(Clone)]
+pub struct PermutationOracle {
+    form: PermutationForm,
+    label: String,
+}
+
+impl PermutationOracle {
+    pub fn bijective(register: Vec<usize>, f: impl Fn(usize) -> usize, label: String) -> Self {
+        assert!(!register.is_empty());
+        let size = 1 << register.len();
+        let table: Vec<usize> = (0..size).map(f).collect();
+        let mut inverse_table = vec![size; size];
+        for (x, y) in table.iter().enumerate() {
+            assert!(
+                *y < size && inverse_table[*y] == size,
+                "map is not a bijection"
+            );
+            inverse_table[*y] = x;
+        }
+        PermutationOracle {
+            form: PermutationForm::Bijective {
+                register,
+                table: Arc::new(table),
+                inverse_table: Arc::new(inverse_table),
+            },
+            label,
+        }
+    }
+
+    pub fn xor(
+        input: Vec<usize>,
+        output: Vec<usize>,
+        f: impl Fn(usize) -> usize,
+        label: String,
+    ) -> Self {
+        assert!(!input.is_empty() && !output.is_empty());
+        for q in input.iter() {
+            assert!(!output.contains(q), "input and output registers overlap");
+        }
+        let table: Vec<usize> = (0..(1 << input.len())).map(f).collect();
+        for y in table.iter() {
+            assert!(
+                y >> output.len() == 0,
+                "f(x) does not fit in the output register"
+            );
+        }
+        PermutationOracle {
+            form: PermutationForm::Xor {
+                input,
+                output,
+                table: Arc::new(table),
+            },
+            label,
+        }
+    }
+
+    fn qubits(&self) -> Vec<usize> {
+        match &self.form {
+            PermutationForm::Bijective { register, .. } => register.clone(),
+            PermutationForm::Xor { input, output, .. } => [&input[..], &output[..]].concat(),
+        }
+    }
+
+    /**
+    The basis state that `idx` is sent to.
+     */
+    fn permute(&self, idx: usize) -> usize {
+        match &self.form {
+            PermutationForm::Bijective {
+                register, table, ..
+            } => with_register_value(register, idx, table[register_value(register, idx)]),
+            PermutationForm::Xor {
+                input,
+                output,
+                table,
+            } => {
+                let y = register_value(output, idx) ^ table[register_value(input, idx)];
+                with_register_value(output, idx, y)
+            }
+        }
+    }
+}
+
+fn register_value(register: &[usize], idx: usize) -> usize {
+    let mut x = 0;
+    for (j, q) in register.iter().enumerate() {
+        x |= ((idx >> q) & 1) << j;
+    }
+    x
+}
+
+fn with_register_value(register: &[usize], idx: usize, x: usize) -> usize {
+    let mut idx = idx;
+    for (j, q) in register.iter().enumerate() {
+        idx = (idx & !(1 << q)) | (((x >> j) & 1) << q);
+    }
+    idx
+}
+
+/**
+Exchange the basis states a and b of `register` with multi-controlled X gates along a Gray code path.
+ */
+fn lower_basis_swap(
+    gate: &dyn Applicable,
+    sink: &mut dyn ElementarySink,
+    register: &[usize],
+    a: usize,
+    b: usize,
+    controls: &[usize],
+) -> Result<(), UnsupportedGate> {
+    let mut path = vec![a];
+    for k in 0..register.len() {
+        if ((a ^ b) >> k) & 1 == 1 {
+            path.push(path[path.len() - 1] ^ (1 << k));
+        }
+    }
+    // swap path[i] and path[i + 1], which differ in a single bit
+    let mut adjacent = |i: usize| -> Result<(), UnsupportedGate> {
+        let k = (path[i] ^ path[i + 1]).trailing_zeros() as usize;
+        let mut bits = Vec::new();
+        let mut polarities = Vec::new();
+        for (j, q) in register.iter().enumerate() {
+            if j != k {
+                bits.push(*q);
+                polarities.push((path[i] >> j) & 1 == 1);
+            }
+        }
+        flip_negative_controls(gate, sink, &bits, &polarities)?;
+        lower_matrix(gate, sink, register[k], X_MATRIX, controls, &bits)?;
+        flip_negative_controls(gate, sink, &bits, &polarities)
+    };
+    let m = path.len() - 1;
+    for i in 0..m {
+        adjacent(i)?;
+    }
+    for i in (0..(m - 1)).rev() {
+        adjacent(i)?;
+    }
+    Ok(())
+}
+
+impl Applicable for PermutationOracle {
+    fn name(&self) -> String {
+        match &self.form {
+            PermutationForm::Bijective { register, .. } => {
+                format!("PermutationOracle[{}]({:?})", self.label, register)
+            }
+            PermutationForm::Xor { input, output, .. } => {
+                format!(
+                    "PermutationOracle[{}]({:?}->{:?})",
+                    self.label, input, output
+                )
+            }
+        }
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = match &self.form {
+            PermutationForm::Bijective { register, .. } => GateInfo::new(
+                GateKind::PermutationOracle,
+                register.clone(),
+                vec![],
+                vec![],
+            ),
+            PermutationForm::Xor { input, output, .. } => GateInfo::new(
+                GateKind::PermutationOracle,
+                output.clone(),
+                input.clone(),
+                vec![],
+            ),
+        };
+        info.label = self.label.clone();
+        info
+    }
+
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        for q in self.qubits().iter() {
+            assert!(iter.mask & (1 << q) == 0, "invalid mask was input.");
         }
+        // the permutation maps the indices of iter onto themselves, so only those are buffered
+        let moved: Vec<(usize, Comp)> = iter
+            .merge(0)
+            .map(|idx| (self.permute(idx), qubits.bits[idx]))
+            .collect();
+        for (new_idx, amp) in moved {
+            qubits.bits[new_idx] = amp;
+        }
+        qubits
+    }
+
+    /**
+    The bijective form is split into transpositions of basis states, the xor form into one
+    multi-controlled X per set bit of f(x).
+     */
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        match &self.form {
+            PermutationForm::Bijective {
+                register, table, ..
+            } => {
+                // at[p]: the original basis state whose amplitude is at p
+                let mut at: Vec<usize> = (0..table.len()).collect();
+                let mut pos: Vec<usize> = (0..table.len()).collect();
+                for x in 0..table.len() {
+                    let (p, q) = (pos[x], table[x]);
+                    if p == q {
+                        continue;
+                    }
+                    lower_basis_swap(self, sink, register, p, q, controls)?;
+                    let y = at[q];
+                    at.swap(p, q);
+                    pos[x] = q;
+                    pos[y] = p;
+                }
+            }
+            PermutationForm::Xor {
+                input,
+                output,
+                table,
+            } => {
+                for (x, y) in table.iter().enumerate() {
+                    let polarities: Vec<bool> =
+                        (0..input.len()).map(|j| (x >> j) & 1 == 1).collect();
+                    for (j, q) in output.iter().enumerate() {
+                        if (y >> j) & 1 == 1 {
+                            flip_negative_controls(self, sink, input, &polarities)?;
+                            lower_matrix(self, sink, *q, X_MATRIX, controls, input)?;
+                            flip_negative_controls(self, sink, input, &polarities)?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
 
-        return qubits;
+impl Inversible for PermutationOracle {
+    fn dagger(&self) -> Box<dyn Operator> {
+        let mut adjoint = self.clone();
+        if let PermutationForm::Bijective {
+            table,
+            inverse_table,
+            ..
+        } = &mut adjoint.form
+        {
+            std::mem::swap(table, inverse_table);
+        }
+        Box::new(adjoint)
     }
 }
+impl Operator for PermutationOracle {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        match &self.form {
+            PermutationForm::Bijective {
+                register,
+                table,
+                inverse_table,
+            } => {
+                let table = permutation_power(table, k);
+                let inverse_table = permutation_power(inverse_table, k);
+                Some(Box::new(PermutationOracle {
+                    form: PermutationForm::Bijective {
+                        register: register.clone(),
+                        table: Arc::new(table),
+                        inverse_table: Arc::new(inverse_table),
+                    },
+                    label: format!("{}^{}", self.label, k),
+                }))
+            }
+            PermutationForm::Xor { .. } => involution_power(self, k),
+        }
+    }
 
-impl Inversible for CNX {}
-impl Operator for CNX {}
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let form = match &self.form {
+            PermutationForm::Bijective {
+                register,
+                table,
+                inverse_table,
+            } => PermutationForm::Bijective {
+                register: remap_bits(register, mapping),
+                table: table.clone(),
+                inverse_table: inverse_table.clone(),
+            },
+            PermutationForm::Xor {
+                input,
+                output,
+                table,
+            } => {
+                let mut bits = remap_bits(&[input.clone(), output.clone()].concat(), mapping);
+                let output = bits.split_off(input.len());
+                PermutationForm::Xor {
+                    input: bits,
+                    output,
+                    table: table.clone(),
+                }
+            }
+        };
+        Some(Box::new(PermutationOracle {
+            form,
+            label: self.label.clone(),
+        }))
+    }
+}
 
 /**
 An alias for handling quantum gates together.
@@ -668,7 +3164,7 @@ impl PushOps for OperatorVec {
 // CU
 /**
 Controlled-Unitary Gate.
-Control a group of arbitrary gates using a specific qubit.
+Control a group of arbitrary gates using a specific qubit, or a list of qubits.
 
 ```
 use Qit::circuits::wrapping_qadd_const;
@@ -704,61 +3200,171 @@ q_out.print_cmps();
 // |1101⟩ : +0.000 +0.000i
 // |1110⟩ : +0.000 +0.000i
 // |1111⟩ : +0.000 +0.000i
+
+// controlled by qubits 3 and 4, firing when 3 is |1⟩ and 4 is |0⟩
+let add_3 = wrapping_qadd_const(&b_in, 3);
+let multi_controlled_add_3 =
+    CU::new_multi_polarized(vec![3, 4], vec![true, false], add_3.gates, String::from("cc-add_3"));
+let q_out = multi_controlled_add_3.apply(Qubits::from_num(5, 0b01001));
+assert_eq!(q_out.bits[0b01100], Comp::new(1.0, 0.0));
 ```
 */
+#[derive(Clone)]
 pub struct CU {
-    controll_bit: usize,
+    controll_bits: Vec<usize>,
+    polarities: Vec<bool>,
     gates: OperatorVec,
     label: String,
 }
 
 impl CU {
     pub fn new(controll_bit: usize, gates: OperatorVec, label: String) -> Self {
-        return CU {
-            controll_bit: controll_bit,
-            gates: gates,
-            label: label,
-        };
+        CU::new_polarized(controll_bit, true, gates, label)
+    }
+
+    /**
+    CU whose control fires on |1⟩ if `polarity` is true and on |0⟩ otherwise.
+     */
+    pub fn new_polarized(
+        controll_bit: usize,
+        polarity: bool,
+        gates: OperatorVec,
+        label: String,
+    ) -> Self {
+        CU::new_multi_polarized(vec![controll_bit], vec![polarity], gates, label)
+    }
+
+    /**
+    CU that fires only when every control bit is |1⟩.
+     */
+    pub fn new_multi(controll_bits: Vec<usize>, gates: OperatorVec, label: String) -> Self {
+        let polarities = vec![true; controll_bits.len()];
+        CU::new_multi_polarized(controll_bits, polarities, gates, label)
+    }
+
+    /**
+    CU whose i-th control fires on |1⟩ if `polarities[i]` is true and on |0⟩ otherwise.
+     */
+    pub fn new_multi_polarized(
+        controll_bits: Vec<usize>,
+        polarities: Vec<bool>,
+        gates: OperatorVec,
+        label: String,
+    ) -> Self {
+        assert!(!controll_bits.is_empty());
+        assert_eq!(controll_bits.len(), polarities.len());
+        CU {
+            controll_bits,
+            polarities,
+            gates,
+            label,
+        }
     }
 
     pub fn from_u(controll_bit: usize, u: U) -> Self {
-        return CU {
-            controll_bit: controll_bit,
-            gates: u.gates,
-            label: u.label,
-        };
+        CU::new(controll_bit, u.gates, u.label)
     }
 }
 
 impl Applicable for CU {
     fn name(&self) -> String {
-        let mut s = format!("CU({}->", self.controll_bit);
+        let labels: Vec<String> = self
+            .controll_bits
+            .iter()
+            .zip(self.polarities.iter())
+            .map(|(c, p)| control_label(*c, *p))
+            .collect();
+        let mut s = format!("CU({}->", labels.join(","));
         for gate in &self.gates {
             s.push_str(&format!("\n{}", gate.name()));
         }
-        return format!("{})", s);
+        format!("{})", s)
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::CU, vec![], self.controll_bits.clone(), vec![]);
+        info.polarities = self.polarities.clone();
+        info.label = self.label.clone();
+        info.children = self.gates.iter().map(|g| g.describe()).collect();
+        info
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
-        let iter = iter.merge(1 << self.controll_bit);
+        let (mask, value) = control_pattern(&self.controll_bits, &self.polarities);
+        let iter = iter.merge_with(mask, value);
         for gate in &self.gates {
             qubits = gate.apply_iter(qubits, &iter);
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        flip_negative_controls(self, sink, &self.controll_bits, &self.polarities)?;
+        let mut controls = controls.to_vec();
+        controls.extend_from_slice(&self.controll_bits);
+        for gate in &self.gates {
+            gate.lower(sink, &controls)?;
+        }
+        flip_negative_controls(self, sink, &self.controll_bits, &self.polarities)
     }
 }
 
 impl Inversible for CU {
-    fn inverse(&mut self) {
-        for g in self.gates.iter_mut() {
-            g.inverse();
-        }
-        self.gates.reverse();
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(CU::new_multi_polarized(
+            self.controll_bits.clone(),
+            self.polarities.clone(),
+            self.gates.iter().rev().map(|g| g.dagger()).collect(),
+            self.label.clone(),
+        ))
     }
 }
 
-impl Operator for CU {}
+impl Operator for CU {
+    /**
+    (C-U)^k = C-(U^k), when the wrapped gates have a native power as a [`U`].
+     */
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let u = U::new(self.gates.clone(), self.label.clone()).power(k)?;
+        Some(Box::new(CU::new_multi_polarized(
+            self.controll_bits.clone(),
+            self.polarities.clone(),
+            vec![u],
+            format!("{}^{}", self.label, k),
+        )))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let gates = self
+            .gates
+            .iter()
+            .map(|g| g.remap(mapping))
+            .collect::<Option<OperatorVec>>()?;
+        let controll_bits = remap_bits(&self.controll_bits, mapping);
+        for g in gates.iter() {
+            for q in g.describe().qubits() {
+                for (c, mapped) in self.controll_bits.iter().zip(controll_bits.iter()) {
+                    assert_ne!(
+                        q, *mapped,
+                        "mapping sends control bit {} and a qubit of {} to the same index",
+                        c, self.label
+                    );
+                }
+            }
+        }
+        Some(Box::new(CU {
+            controll_bits,
+            polarities: self.polarities.clone(),
+            gates,
+            label: self.label.clone(),
+        }))
+    }
+}
 
 /**
 Unitary struct. struct for applying a vector of arbitrary gates together to a qubit
@@ -779,6 +3385,7 @@ let adder = U::new(vec![Box::new(ccx1), Box::new(cx1), Box::new(ccx2), Box::new(
         String::from("full_adder_bit"));
 ```
  */
+#[derive(Clone)]
 pub struct U {
     pub gates: OperatorVec,
     label: String,
@@ -786,15 +3393,34 @@ pub struct U {
 
 impl U {
     pub fn new(gates: OperatorVec, name: String) -> Self {
-        return U {
-            gates: gates,
-            label: name,
-        };
+        U { gates, label: name }
     }
 
     pub fn rename(&mut self, name: String) {
         self.label = name;
     }
+
+    /**
+    The inverse circuit as a `U`, built from the `dagger` of every gate in reverse order.
+    `self` is left unchanged.
+     */
+    pub fn adjoint(&self) -> U {
+        let gates: OperatorVec = self.gates.iter().rev().map(|g| g.dagger()).collect();
+        U::new(gates, self.label.clone())
+    }
+
+    /**
+    The circuit moved up by `offset` qubits, as a `U`. Same as `remap` with q → q + offset,
+    so `None` if one of its gates cannot be relabelled.
+     */
+    pub fn offset(&self, offset: usize) -> Option<U> {
+        let gates = self
+            .gates
+            .iter()
+            .map(|g| g.remap(&|q| q + offset))
+            .collect::<Option<OperatorVec>>()?;
+        Some(U::new(gates, self.label.clone()))
+    }
 }
 
 impl Applicable for U {
@@ -803,25 +3429,170 @@ impl Applicable for U {
         for gate in &self.gates {
             s.push_str(&format!("\n{}", gate.name()));
         }
-        return format!("{})", s);
+        format!("{})", s)
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::U, vec![], vec![], vec![]);
+        info.label = self.label.clone();
+        info.children = self.gates.iter().map(|g| g.describe()).collect();
+        info
     }
 
     fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
         for gate in &self.gates {
-            qubits = gate.apply_iter(qubits, &iter);
+            qubits = gate.apply_iter(qubits, iter);
         }
 
-        return qubits;
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        for gate in &self.gates {
+            gate.lower(sink, controls)?;
+        }
+        Ok(())
     }
 }
 
 impl Inversible for U {
-    fn inverse(&mut self) {
-        for g in self.gates.iter_mut() {
-            g.inverse();
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(self.adjoint())
+    }
+}
+
+impl Operator for U {
+    /**
+    U^k = g_1^k ... g_m^k when every gate has a native power and the gates commute pairwise,
+    i.e. any two of them are both diagonal or act on disjoint qubits.
+     */
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let infos: Vec<GateInfo> = self.gates.iter().map(|g| g.describe()).collect();
+        if infos.iter().any(is_opaque) {
+            return None;
+        }
+        for i in 0..infos.len() {
+            for j in 0..i {
+                let qubits = infos[j].qubits();
+                let disjoint = infos[i].qubits().iter().all(|q| !qubits.contains(q));
+                let commute = disjoint || (is_diagonal(&infos[i]) && is_diagonal(&infos[j]));
+                if !commute {
+                    return None;
+                }
+            }
+        }
+        let gates = self
+            .gates
+            .iter()
+            .map(|g| g.power(k))
+            .collect::<Option<OperatorVec>>()?;
+        Some(Box::new(U::new(gates, format!("{}^{}", self.label, k))))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        let gates = self
+            .gates
+            .iter()
+            .map(|g| g.remap(mapping))
+            .collect::<Option<OperatorVec>>()?;
+        Some(Box::new(U::new(gates, self.label.clone())))
+    }
+}
+
+/**
+Operator applied k times.
+
+When the operator provides a native power through [`Operator::power`] (angle gates, phase and
+permutation oracles, MatrixGate and U3, self-inverse gates, SX and ISWAP, U whose gates commute and
+all have native powers, and CU over such a U), that single gate is applied instead of k copies.
+
+# Usage
+```
+use Qit::core::{Applicable, Qubits};
+use Qit::gates::{Pow, CU, R};
+use std::f64::consts::PI;
+
+// controlled-R^(2^10): one gate instead of 1024
+let r_pow = Pow::new(Box::new(R::new(1, PI / 1024.0)), 1 << 10);
+let cu = CU::new(0, vec![Box::new(r_pow)], String::from("cu"));
+let q_out = cu.apply(Qubits::from_num(2, 0b11));
+assert!((q_out.bits[0b11].0 + 1.0).abs() < 1e-9);
+```
+ */
+#[derive(Clone)]
+pub struct Pow {
+    op: Box<dyn Operator>,
+    k: usize,
+    fast: Option<Box<dyn Operator>>,
+}
+
+impl Pow {
+    pub fn new(op: Box<dyn Operator>, k: usize) -> Self {
+        let fast = op.power(k);
+        Pow { op, k, fast }
+    }
+}
+
+impl Applicable for Pow {
+    fn name(&self) -> String {
+        format!("Pow[{}]({})", self.k, self.op.name())
+    }
+
+    fn describe(&self) -> GateInfo {
+        let mut info = GateInfo::new(GateKind::Pow, vec![], vec![], vec![self.k as f64]);
+        info.children = vec![self.op.describe()];
+        info
+    }
+
+    fn apply_iter(&self, mut qubits: Qubits, iter: &BitSlideIndex) -> Qubits {
+        if let Some(fast) = &self.fast {
+            return fast.apply_iter(qubits, iter);
+        }
+        for _ in 0..self.k {
+            qubits = self.op.apply_iter(qubits, iter);
+        }
+        qubits
+    }
+
+    fn lower(
+        &self,
+        sink: &mut dyn ElementarySink,
+        controls: &[usize],
+    ) -> Result<(), UnsupportedGate> {
+        if let Some(fast) = &self.fast {
+            return fast.lower(sink, controls);
         }
-        self.gates.reverse();
+        for _ in 0..self.k {
+            self.op.lower(sink, controls)?;
+        }
+        Ok(())
+    }
+}
+
+impl Inversible for Pow {
+    /**
+    (U^k)† = (U†)^k
+     */
+    fn dagger(&self) -> Box<dyn Operator> {
+        Box::new(Pow {
+            op: self.op.dagger(),
+            k: self.k,
+            fast: self.fast.as_ref().map(|f| f.dagger()),
+        })
     }
 }
 
-impl Operator for U {}
+impl Operator for Pow {
+    fn power(&self, k: usize) -> Option<Box<dyn Operator>> {
+        let k = self.k.checked_mul(k)?;
+        Some(Box::new(Pow::new(self.op.clone(), k)))
+    }
+
+    fn remap(&self, mapping: &dyn Fn(usize) -> usize) -> Option<Box<dyn Operator>> {
+        Some(Box::new(Pow::new(self.op.remap(mapping)?, self.k)))
+    }
+}
@@ -0,0 +1,266 @@
+/*!
+ Gradients of expectation values of parameterised circuits.
+
+ For a [`ParameterizedCircuit`] C, an input state |ψ0⟩ and an [`Observable`] O, the functions of this
+ module differentiate f = ⟨ψ0|C(values)† O C(values)|ψ0⟩ with respect to every symbol.
+
+ * [`parameter_shift_gradient`] runs the circuit twice per angle, with the angle shifted by ±π/2.
+ * [`adjoint_gradient`] runs the circuit once and then sweeps back through the gate list, which costs
+   about as much as three evaluations of f regardless of the number of angles.
+
+ Both are exact for every gate of [`ParamGate`].
+
+# Example
+```
+use std::collections::HashMap;
+use Qit::core::Qubits;
+use Qit::gradient::{adjoint_gradient, parameter_shift_gradient};
+use Qit::observable::{Observable, Pauli, PauliString};
+use Qit::params::{Angle, ParamGate, ParameterizedCircuit};
+
+// f(a) = ⟨0|RX(a)† Z RX(a)|0⟩ = cos(a), so df/da = -sin(a)
+let mut circ = ParameterizedCircuit::new(String::from("rx"));
+circ.push_param(ParamGate::RX(0, Angle::symbol("a")));
+let mut obs = Observable::new();
+obs.add_term(1.0, PauliString::new(vec![(0, Pauli::Z)]));
+
+let mut values = HashMap::new();
+values.insert(String::from("a"), 0.3);
+let input = Qubits::zeros(1);
+let shift = parameter_shift_gradient(&circ, &obs, &input, &values);
+let adjoint = adjoint_gradient(&circ, &obs, &input, &values);
+assert!((shift["a"] + 0.3_f64.sin()).abs() < 1e-9);
+assert!((adjoint["a"] + 0.3_f64.sin()).abs() < 1e-9);
+```
+*/
+
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+use super::core::{Applicable, Comp, Operator, Qubits};
+use super::gates::{CPhase, OperatorVec, R, RX, RY, RZ, U};
+use super::observable::{inner_re, Observable};
+use super::params::{Angle, Element, ParamGate, ParameterizedCircuit};
+use super::pauli::{Pauli, PauliString};
+
+/**
+⟨ψ0|C(values)† O C(values)|ψ0⟩
+ */
+pub fn expectation(
+    circ: &ParameterizedCircuit,
+    obs: &Observable,
+    input: &Qubits,
+    values: &HashMap<String, f64>,
+) -> f64 {
+    obs.expectation(&circ.bind(values).apply(input.clone()))
+}
+
+/**
+Gradient by the parameter-shift rule. An angle `scale * symbol + offset` contributes
+`scale * (f(angle + π/2) - f(angle - π/2)) / 2` to the derivative of its symbol.
+ */
+pub fn parameter_shift_gradient(
+    circ: &ParameterizedCircuit,
+    obs: &Observable,
+    input: &Qubits,
+    values: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    let mut grad = zero_gradient(circ);
+    for (k, element) in circ.elements.iter().enumerate() {
+        let gate = match element {
+            Element::Param(gate) => gate,
+            Element::Fixed(_) => continue,
+        };
+        for (j, angle) in gate.angles().iter().enumerate() {
+            let name = match &angle.symbol {
+                Some(name) => name,
+                None => continue,
+            };
+            let plus = shifted(circ, values, k, j, 0.5 * PI);
+            let minus = shifted(circ, values, k, j, -0.5 * PI);
+            let diff = obs.expectation(&plus.apply(input.clone()))
+                - obs.expectation(&minus.apply(input.clone()));
+            *grad.get_mut(name).unwrap() += angle.scale * 0.5 * diff;
+        }
+    }
+    grad
+}
+
+/**
+Gradient by adjoint differentiation.
+
+Let |φk⟩ be the state after the k-th gate, dUk/dθ = D Uk, and ⟨λk| = ⟨φN|O U_N…U_(k+1).
+Then df/dθ = 2 Re⟨λk|D|φk⟩. Both states are obtained from the final ones by undoing one gate at a time.
+ */
+pub fn adjoint_gradient(
+    circ: &ParameterizedCircuit,
+    obs: &Observable,
+    input: &Qubits,
+    values: &HashMap<String, f64>,
+) -> HashMap<String, f64> {
+    let mut steps: Vec<Step> = Vec::new();
+    for element in circ.elements.iter() {
+        match element {
+            Element::Fixed(op) => steps.push(Step::Gate(op.clone())),
+            Element::Param(gate) => push_steps(&mut steps, gate, values),
+        }
+    }
+
+    let mut phi = input.clone();
+    for step in steps.iter() {
+        phi = step.gate().apply(phi);
+    }
+    let mut lambda = obs.apply(&phi);
+
+    let mut grad = zero_gradient(circ);
+    for step in steps.iter().rev() {
+        if let Step::Rotation(
+            _,
+            generator,
+            Angle {
+                symbol: Some(name),
+                scale,
+                ..
+            },
+        ) = step
+        {
+            let d_phi = generator.apply(&phi);
+            *grad.get_mut(name).unwrap() += scale * 2.0 * inner_re(&lambda, &d_phi);
+        }
+        let inv = step.gate().dagger();
+        phi = inv.apply(phi);
+        lambda = inv.apply(lambda);
+    }
+    grad
+}
+
+fn zero_gradient(circ: &ParameterizedCircuit) -> HashMap<String, f64> {
+    circ.parameters().into_iter().map(|n| (n, 0.0)).collect()
+}
+
+/**
+The circuit with the j-th angle of the k-th element shifted by `shift`.
+ */
+fn shifted(
+    circ: &ParameterizedCircuit,
+    values: &HashMap<String, f64>,
+    k: usize,
+    j: usize,
+    shift: f64,
+) -> U {
+    let mut gates: OperatorVec = Vec::with_capacity(circ.elements.len());
+    for (i, element) in circ.elements.iter().enumerate() {
+        match element {
+            Element::Fixed(op) => gates.push(op.clone()),
+            Element::Param(gate) if i == k => {
+                let mut gate = gate.clone();
+                gate.angles_mut()[j].offset += shift;
+                gates.push(gate.bind(values));
+            }
+            Element::Param(gate) => gates.push(gate.bind(values)),
+        }
+    }
+    U::new(gates, circ.label.clone())
+}
+
+/**
+dU/dθ = D U for a gate U(θ) = exp(θD), up to a global phase that does not change f.
+ */
+enum Generator {
+    // -i/2 P on a qubit, for RX, RY and RZ
+    Pauli(usize, Pauli),
+    // i|1..1⟩⟨1..1| on the qubits of the mask, for R and CPhase
+    Projector(usize),
+}
+
+impl Generator {
+    fn apply(&self, qubits: &Qubits) -> Qubits {
+        match self {
+            Generator::Pauli(target, p) => {
+                let mut out = PauliString::new(vec![(*target, *p)]).apply(qubits);
+                for b in out.bits.iter_mut() {
+                    *b = *b * Comp(0.0, -0.5);
+                }
+                out
+            }
+            Generator::Projector(mask) => {
+                let mut out = qubits.clone();
+                for (index, b) in out.bits.iter_mut().enumerate() {
+                    *b = if index & mask == *mask {
+                        *b * Comp(0.0, 1.0)
+                    } else {
+                        Comp::zero()
+                    };
+                }
+                out
+            }
+        }
+    }
+}
+
+enum Step {
+    Gate(Box<dyn Operator>),
+    Rotation(Box<dyn Operator>, Generator, Angle),
+}
+
+impl Step {
+    fn gate(&self) -> &dyn Operator {
+        match self {
+            Step::Gate(op) | Step::Rotation(op, _, _) => op.as_ref(),
+        }
+    }
+}
+
+/**
+Expand a parameterised gate into rotations with a single generator each.
+U3(θ, φ, λ) equals RZ(φ)RY(θ)RZ(λ) up to a global phase.
+ */
+fn push_steps(steps: &mut Vec<Step>, gate: &ParamGate, values: &HashMap<String, f64>) {
+    let rot = |op: Box<dyn Operator>, generator: Generator, angle: &Angle| {
+        Step::Rotation(op, generator, angle.clone())
+    };
+    match gate {
+        ParamGate::R(t, a) => steps.push(rot(
+            Box::new(R::new(*t, a.resolve(values))),
+            Generator::Projector(1 << t),
+            a,
+        )),
+        ParamGate::RX(t, a) => steps.push(rot(
+            Box::new(RX::new(*t, a.resolve(values))),
+            Generator::Pauli(*t, Pauli::X),
+            a,
+        )),
+        ParamGate::RY(t, a) => steps.push(rot(
+            Box::new(RY::new(*t, a.resolve(values))),
+            Generator::Pauli(*t, Pauli::Y),
+            a,
+        )),
+        ParamGate::RZ(t, a) => steps.push(rot(
+            Box::new(RZ::new(*t, a.resolve(values))),
+            Generator::Pauli(*t, Pauli::Z),
+            a,
+        )),
+        ParamGate::CPhase(c, t, a) => steps.push(rot(
+            Box::new(CPhase::new(*c, *t, a.resolve(values))),
+            Generator::Projector((1 << c) | (1 << t)),
+            a,
+        )),
+        ParamGate::U3(t, theta, phi, lambda) => {
+            steps.push(rot(
+                Box::new(RZ::new(*t, lambda.resolve(values))),
+                Generator::Pauli(*t, Pauli::Z),
+                lambda,
+            ));
+            steps.push(rot(
+                Box::new(RY::new(*t, theta.resolve(values))),
+                Generator::Pauli(*t, Pauli::Y),
+                theta,
+            ));
+            steps.push(rot(
+                Box::new(RZ::new(*t, phi.resolve(values))),
+                Generator::Pauli(*t, Pauli::Z),
+                phi,
+            ));
+        }
+    }
+}
@@ -80,9 +80,23 @@ q_out.print_cmps();
 // |111⟩ : +0.000 +0.000i
 ```
 */
+// the crate name "Qit" is not snake case
+#![allow(non_snake_case)]
 
 pub mod circuits;
+pub mod classical;
 pub mod core;
+pub mod density;
+pub mod equivalence;
 pub mod gates;
+pub mod gradient;
+pub mod mps;
+pub mod observable;
+pub mod optim;
+pub mod params;
+pub mod pauli;
+pub mod sparse;
+pub mod stabilizer;
 #[cfg(test)]
 mod tests;
+pub mod unitary;
@@ -0,0 +1,593 @@
+/*!
+ Matrix product state (MPS) simulator.
+
+ Each qubit is a tensor A[l][s][r] connected to its neighbours by bonds. States with little
+ entanglement, such as GHZ states or shallow circuits, need only small bonds and can be simulated
+ with far more qubits than [`Qubits`] can hold.
+
+ Gates are read through [`Applicable::lower`]. One-qubit gates are contracted into a single tensor,
+ and singly-controlled gates (CX, CU with one gate, ...) become two-qubit gates. Two-qubit gates on
+ distant qubits are routed with SWAPs until the qubits are adjacent. After each two-qubit gate the
+ bond is split by SVD and truncated to `max_bond` singular values; the discarded weight is
+ accumulated in [`MatrixProductState::truncation_error`].
+
+ Gates with k > 1 controls (CCX, CNX, MCZ, CSWAP, the oracles, ...) are routed the same way onto
+ k + 1 consecutive sites, applied to the contracted block and split again site by site. The block
+ holds 2^(k+1) times the squared bond dimension amplitudes, so many controls are expensive.
+
+# Example
+```
+use Qit::core::QuantumState;
+use Qit::gates::{CX, H};
+use Qit::mps::MatrixProductState;
+
+// 64-qubit GHZ state with bond dimension 2
+let mut mps = MatrixProductState::new(64, 2);
+mps.apply(&H::new(0)).unwrap();
+for i in 1..64 {
+    mps.apply(&CX::new(0, i)).unwrap();
+}
+assert!(mps.truncation_error() < 1e-12);
+assert!((mps.amplitude(0).0 - 0.5_f64.sqrt()).abs() < 1e-9);
+assert!((mps.amplitude(usize::MAX).0 - 0.5_f64.sqrt()).abs() < 1e-9);
+```
+*/
+
+use super::core::{
+    Applicable, Comp, ElementarySink, Matrix2, QuantumState, Qubits, UnsupportedGate,
+};
+
+const SVD_EPS: f64 = 1e-14;
+const MAX_SWEEPS: usize = 100;
+
+/**
+4x4 matrix on two qubits (a, b), indexed by 2 * a + b.
+*/
+type Matrix4 = [[Comp; 4]; 4];
+
+/**
+Tensor of one site with shape (left, 2, right).
+*/
+#[derive(Clone)]
+struct Site {
+    left: usize,
+    right: usize,
+    data: Vec<Comp>,
+}
+
+impl Site {
+    fn get(&self, l: usize, s: usize, r: usize) -> Comp {
+        self.data[(l * 2 + s) * self.right + r]
+    }
+}
+
+/**
+Matrix product state of `size` qubits. Site i holds qubit i.
+*/
+#[derive(Clone)]
+pub struct MatrixProductState {
+    pub size: usize,
+    pub max_bond: usize,
+    sites: Vec<Site>,
+    truncation_error: f64,
+}
+
+impl MatrixProductState {
+    /**
+    |0...0⟩ with bonds truncated to `max_bond`.
+    */
+    pub fn new(size: usize, max_bond: usize) -> Self {
+        assert!(size > 0);
+        assert!(max_bond > 0);
+        let site = Site {
+            left: 1,
+            right: 1,
+            data: vec![Comp(1.0, 0.0), Comp::zero()],
+        };
+        MatrixProductState {
+            size,
+            max_bond,
+            sites: vec![site; size],
+            truncation_error: 0.0,
+        }
+    }
+
+    /**
+    Total weight (sum of squared singular values) discarded by truncation so far.
+    */
+    pub fn truncation_error(&self) -> f64 {
+        self.truncation_error
+    }
+
+    /**
+    Dimension of each of the size - 1 bonds.
+    */
+    pub fn bond_dimensions(&self) -> Vec<usize> {
+        self.sites[..self.size - 1]
+            .iter()
+            .map(|s| s.right)
+            .collect()
+    }
+
+    /**
+    Amplitude of the basis state |index⟩. Qubits beyond the width of usize are taken as |0⟩.
+    */
+    pub fn amplitude(&self, index: usize) -> Comp {
+        let mut v = vec![Comp(1.0, 0.0)];
+        for (i, site) in self.sites.iter().enumerate() {
+            let s = if i < usize::BITS as usize {
+                (index >> i) & 1
+            } else {
+                0
+            };
+            let mut next = vec![Comp::zero(); site.right];
+            for (l, vl) in v.iter().enumerate() {
+                for (r, nr) in next.iter_mut().enumerate() {
+                    *nr = *nr + *vl * site.get(l, s, r);
+                }
+            }
+            v = next;
+        }
+        v[0]
+    }
+
+    /**
+    Convert to dense qubits. Requires 2^size amplitudes of memory.
+    */
+    pub fn to_qubits(&self) -> Qubits {
+        let bits = (0..(1 << self.size)).map(|i| self.amplitude(i)).collect();
+        Qubits::from_bits(self.size, bits)
+    }
+
+    /**
+    Probability of observing |1⟩ on `target`.
+    */
+    pub fn prob_one(&self, target: usize) -> f64 {
+        self.expect_projector(&[(target, 1)]) / self.expect_projector(&[])
+    }
+
+    /**
+    ⟨ψ|P|ψ⟩ where P projects each (qubit, value) of `projections` onto |value⟩.
+    */
+    fn expect_projector(&self, projections: &[(usize, usize)]) -> f64 {
+        let mut env = vec![Comp(1.0, 0.0)];
+        for (i, site) in self.sites.iter().enumerate() {
+            let mut next = vec![Comp::zero(); site.right * site.right];
+            for s in 0..2 {
+                if projections.iter().any(|(q, v)| *q == i && *v != s) {
+                    continue;
+                }
+                for l0 in 0..site.left {
+                    for l1 in 0..site.left {
+                        let e = env[l0 * site.left + l1];
+                        if e.abs_square() == 0.0 {
+                            continue;
+                        }
+                        for r0 in 0..site.right {
+                            let a = conj(site.get(l0, s, r0)) * e;
+                            for r1 in 0..site.right {
+                                next[r0 * site.right + r1] =
+                                    next[r0 * site.right + r1] + a * site.get(l1, s, r1);
+                            }
+                        }
+                    }
+                }
+            }
+            env = next;
+        }
+        env[0].0
+    }
+
+    fn apply_single(&mut self, target: usize, matrix: &Matrix2) {
+        let site = &mut self.sites[target];
+        for l in 0..site.left {
+            for r in 0..site.right {
+                let a0 = site.get(l, 0, r);
+                let a1 = site.get(l, 1, r);
+                site.data[(l * 2) * site.right + r] = matrix[0][0] * a0 + matrix[0][1] * a1;
+                site.data[(l * 2 + 1) * site.right + r] = matrix[1][0] * a0 + matrix[1][1] * a1;
+            }
+        }
+    }
+
+    /**
+    Apply `matrix` to qubits (a, b), moving a next to b with SWAPs and back afterwards.
+    */
+    fn apply_pair(&mut self, a: usize, b: usize, matrix: &Matrix4) {
+        assert!(a != b);
+        let mut pos = a;
+        while pos + 1 < b {
+            self.apply_adjacent(pos, &SWAP);
+            pos += 1;
+        }
+        while pos > b + 1 {
+            self.apply_adjacent(pos - 1, &SWAP);
+            pos -= 1;
+        }
+        if pos < b {
+            self.apply_adjacent(pos, matrix);
+        } else {
+            self.apply_adjacent(b, &reverse(matrix));
+        }
+        while pos < a {
+            self.apply_adjacent(pos, &SWAP);
+            pos += 1;
+        }
+        while pos > a {
+            self.apply_adjacent(pos - 1, &SWAP);
+            pos -= 1;
+        }
+    }
+
+    /**
+    Apply `matrix` to sites (i, i + 1), then split the bond with a truncated SVD.
+    */
+    fn apply_adjacent(&mut self, i: usize, matrix: &Matrix4) {
+        let (a, b) = (&self.sites[i], &self.sites[i + 1]);
+        let (left, mid, right) = (a.left, a.right, b.right);
+
+        // theta[l][s1][s2][r]
+        let mut theta = vec![Comp::zero(); left * 4 * right];
+        for l in 0..left {
+            for s1 in 0..2 {
+                for k in 0..mid {
+                    let x = a.get(l, s1, k);
+                    if x.abs_square() == 0.0 {
+                        continue;
+                    }
+                    for s2 in 0..2 {
+                        for r in 0..right {
+                            let idx = ((l * 2 + s1) * 2 + s2) * right + r;
+                            theta[idx] = theta[idx] + x * b.get(k, s2, r);
+                        }
+                    }
+                }
+            }
+        }
+        let mut applied = vec![Comp::zero(); left * 4 * right];
+        for l in 0..left {
+            for r in 0..right {
+                for out in 0..4 {
+                    let mut sum = Comp::zero();
+                    for inp in 0..4 {
+                        sum = sum + matrix[out][inp] * theta[(l * 4 + inp) * right + r];
+                    }
+                    applied[(l * 4 + out) * right + r] = sum;
+                }
+            }
+        }
+
+        // rows (l, s1), columns (s2, r)
+        let (keep, b_data) = self.split(i, &applied, left, 2 * right);
+        self.sites[i + 1] = Site {
+            left: keep,
+            right,
+            data: b_data,
+        };
+    }
+
+    /**
+    Apply `matrix` to `target` when all `controls` are |1⟩, on the m consecutive sites from `start`.
+    `target` and `controls` are positions within the block.
+
+    The sites are contracted into one tensor of size left * 2^m * right, so the cost grows as 2^m.
+    */
+    fn apply_block(
+        &mut self,
+        start: usize,
+        m: usize,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) {
+        let left = self.sites[start].left;
+
+        // theta[(l, s_0, ..., s_t)][r], with s_0 the most significant bit
+        let mut theta = self.sites[start].data.clone();
+        let (mut rows, mut right) = (left * 2, self.sites[start].right);
+        for site in &self.sites[(start + 1)..(start + m)] {
+            let mut next = vec![Comp::zero(); rows * 2 * site.right];
+            for row in 0..rows {
+                for k in 0..right {
+                    let x = theta[row * right + k];
+                    if x.abs_square() == 0.0 {
+                        continue;
+                    }
+                    for s in 0..2 {
+                        for r in 0..site.right {
+                            let idx = (row * 2 + s) * site.right + r;
+                            next[idx] = next[idx] + x * site.get(k, s, r);
+                        }
+                    }
+                }
+            }
+            theta = next;
+            rows *= 2;
+            right = site.right;
+        }
+
+        let bit = |p: usize| 1 << (m - 1 - p);
+        let mask = controls.iter().fold(0, |acc, c| acc | bit(*c));
+        for l in 0..left {
+            for s in 0..(1 << m) {
+                if s & mask != mask || s & bit(target) != 0 {
+                    continue;
+                }
+                for r in 0..right {
+                    let i0 = ((l << m) | s) * right + r;
+                    let i1 = ((l << m) | s | bit(target)) * right + r;
+                    let (a0, a1) = (theta[i0], theta[i1]);
+                    theta[i0] = matrix[0][0] * a0 + matrix[0][1] * a1;
+                    theta[i1] = matrix[1][0] * a0 + matrix[1][1] * a1;
+                }
+            }
+        }
+
+        // peel off one site at a time: rows (l, s_t), columns (s_t+1, ..., r)
+        let mut l = left;
+        for t in 0..(m - 1) {
+            let cols = (1 << (m - 1 - t)) * right;
+            let (keep, rest) = self.split(start + t, &theta, l, cols);
+            theta = rest;
+            l = keep;
+        }
+        self.sites[start + m - 1] = Site {
+            left: l,
+            right,
+            data: theta,
+        };
+    }
+
+    /**
+    Split `theta`, a matrix with rows (l, s) and `cols` columns, by an SVD truncated to `max_bond`.
+    Site i becomes U, and σV† is returned together with the kept bond dimension.
+    */
+    fn split(&mut self, i: usize, theta: &[Comp], left: usize, cols: usize) -> (usize, Vec<Comp>) {
+        let rows = left * 2;
+        let (u, sigma, v) = svd(theta, rows, cols);
+        let total: f64 = sigma.iter().map(|s| s * s).sum();
+        let keep = sigma
+            .iter()
+            .filter(|s| **s > SVD_EPS * sigma[0])
+            .count()
+            .clamp(1, self.max_bond);
+        let kept: f64 = sigma[..keep].iter().map(|s| s * s).sum();
+        // a zero block has nothing to discard and nothing to renormalize
+        let norm = if total > 0.0 {
+            self.truncation_error += (total - kept) / total;
+            (total / kept).sqrt()
+        } else {
+            1.0
+        };
+
+        let mut a_data = vec![Comp::zero(); rows * keep];
+        for row in 0..rows {
+            for j in 0..keep {
+                a_data[row * keep + j] = u[row * cols + j];
+            }
+        }
+        let mut b_data = vec![Comp::zero(); keep * cols];
+        for j in 0..keep {
+            for col in 0..cols {
+                b_data[j * cols + col] = conj(v[col * cols + j]) * (sigma[j] * norm);
+            }
+        }
+        self.sites[i] = Site {
+            left,
+            right: keep,
+            data: a_data,
+        };
+        (keep, b_data)
+    }
+
+    /**
+    Apply a gate with several controls: the qubits are moved next to each other with SWAPs,
+    the gate is applied as one block and the SWAPs are undone.
+    */
+    fn apply_multi_controlled(&mut self, target: usize, matrix: &Matrix2, controls: &[usize]) {
+        let mut qubits: Vec<usize> = controls.to_vec();
+        qubits.push(target);
+        qubits.sort();
+        let start = qubits[0];
+        let mut swaps = Vec::new();
+        for (t, q) in qubits.iter().enumerate() {
+            for p in ((start + t)..*q).rev() {
+                self.apply_adjacent(p, &SWAP);
+                swaps.push(p);
+            }
+        }
+        let position = |q: usize| qubits.iter().position(|x| *x == q).unwrap();
+        let block_controls: Vec<usize> = controls.iter().map(|c| position(*c)).collect();
+        self.apply_block(
+            start,
+            qubits.len(),
+            position(target),
+            matrix,
+            &block_controls,
+        );
+        for p in swaps.iter().rev() {
+            self.apply_adjacent(*p, &SWAP);
+        }
+    }
+}
+
+impl QuantumState for MatrixProductState {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let mut state = self.clone();
+        op.lower(&mut state, &[])?;
+        *self = state;
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        use rand::prelude::*;
+        let p1 = self.prob_one(target);
+        let outcome = rand::thread_rng().gen::<f64>() < p1;
+        let (s, p) = if outcome { (1, p1) } else { (0, 1.0 - p1) };
+        let site = &mut self.sites[target];
+        for l in 0..site.left {
+            for r in 0..site.right {
+                let idx = (l * 2 + (1 - s)) * site.right + r;
+                site.data[idx] = Comp::zero();
+                let idx = (l * 2 + s) * site.right + r;
+                site.data[idx] = site.data[idx] * (1.0 / p.sqrt());
+            }
+        }
+        outcome
+    }
+
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        let norm = self.expect_projector(&[]);
+        let mut probs = vec![0.0; 1 << tar.len()];
+        for (tar_idx, prob) in probs.iter_mut().enumerate() {
+            let projections: Vec<(usize, usize)> = tar
+                .iter()
+                .enumerate()
+                .map(|(j, t)| (*t, (tar_idx >> j) & 1))
+                .collect();
+            *prob = self.expect_projector(&projections) / norm;
+        }
+        probs
+    }
+}
+
+impl ElementarySink for MatrixProductState {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        assert!(target < self.size);
+        match controls {
+            [] => self.apply_single(target, matrix),
+            [c] => {
+                assert!(*c < self.size && *c != target);
+                let mut m = identity4();
+                for i in 0..2 {
+                    for j in 0..2 {
+                        m[2 + i][2 + j] = matrix[i][j];
+                    }
+                }
+                self.apply_pair(*c, target, &m);
+            }
+            _ => {
+                for (i, c) in controls.iter().enumerate() {
+                    assert!(*c < self.size && *c != target);
+                    assert!(!controls[..i].contains(c));
+                }
+                self.apply_multi_controlled(target, matrix, controls);
+            }
+        }
+        Ok(())
+    }
+}
+
+const SWAP: Matrix4 = {
+    let (o, l) = (Comp(0.0, 0.0), Comp(1.0, 0.0));
+    [[l, o, o, o], [o, o, l, o], [o, l, o, o], [o, o, o, l]]
+};
+
+fn identity4() -> Matrix4 {
+    let mut m = [[Comp::zero(); 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = Comp(1.0, 0.0);
+    }
+    m
+}
+
+/**
+The same two-qubit matrix with the roles of the qubits exchanged.
+*/
+fn reverse(m: &Matrix4) -> Matrix4 {
+    let p = [0, 2, 1, 3];
+    let mut out = [[Comp::zero(); 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            out[p[i]][p[j]] = m[i][j];
+        }
+    }
+    out
+}
+
+fn conj(c: Comp) -> Comp {
+    Comp(c.0, -c.1)
+}
+
+/**
+Singular value decomposition M = U diag(σ) V† of a row-major rows x cols matrix by one-sided Jacobi.
+
+Returns U (rows x k), σ (k, descending) and V (cols x k) with k = cols, all row-major.
+*/
+fn svd(m: &[Comp], rows: usize, cols: usize) -> (Vec<Comp>, Vec<f64>, Vec<Comp>) {
+    let mut a = m.to_vec();
+    let mut v = vec![Comp::zero(); cols * cols];
+    for i in 0..cols {
+        v[i * cols + i] = Comp(1.0, 0.0);
+    }
+
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..cols {
+            for q in (p + 1)..cols {
+                let (mut alpha, mut beta, mut gamma) = (0.0, 0.0, Comp::zero());
+                for i in 0..rows {
+                    let (ap, aq) = (a[i * cols + p], a[i * cols + q]);
+                    alpha += ap.abs_square();
+                    beta += aq.abs_square();
+                    gamma = gamma + conj(ap) * aq;
+                }
+                let g = gamma.0.hypot(gamma.1);
+                if g <= SVD_EPS * (alpha * beta).sqrt() || g == 0.0 {
+                    continue;
+                }
+                rotated = true;
+                // remove the phase of γ from column q, then rotate as in the real case
+                let phase = Comp(gamma.0 / g, -gamma.1 / g);
+                let zeta = (beta - alpha) / (2.0 * g);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                for (mat, n) in [(&mut a, rows), (&mut v, cols)] {
+                    for i in 0..n {
+                        let xp = mat[i * cols + p];
+                        let xq = mat[i * cols + q] * phase;
+                        mat[i * cols + p] = xp * c - xq * s;
+                        mat[i * cols + q] = xp * s + xq * c;
+                    }
+                }
+            }
+        }
+        if !rotated {
+            break;
+        }
+    }
+
+    let mut order: Vec<(f64, usize)> = (0..cols)
+        .map(|j| {
+            let norm: f64 = (0..rows).map(|i| a[i * cols + j].abs_square()).sum();
+            (norm.sqrt(), j)
+        })
+        .collect();
+    order.sort_by(|x, y| y.0.partial_cmp(&x.0).unwrap());
+
+    let mut u = vec![Comp::zero(); rows * cols];
+    let mut sorted_v = vec![Comp::zero(); cols * cols];
+    let mut sigma = Vec::with_capacity(cols);
+    for (k, (s, j)) in order.iter().enumerate() {
+        sigma.push(*s);
+        for i in 0..rows {
+            if *s > 0.0 {
+                u[i * cols + k] = a[i * cols + j] * (1.0 / s);
+            }
+        }
+        for i in 0..cols {
+            sorted_v[i * cols + k] = v[i * cols + j];
+        }
+    }
+    (u, sigma, sorted_v)
+}
@@ -0,0 +1,86 @@
+/*!
+ Observables built from Pauli strings.
+
+ An [`Observable`] is a real linear combination of [`PauliString`]s, such as the Hamiltonians used
+ in VQE and QAOA. [`Pauli`] and [`PauliString`] live in the [`pauli`](crate::pauli) module and are
+ re-exported here.
+
+# Example
+```
+use Qit::core::{Applicable, Qubits};
+use Qit::gates::{CX, H};
+use Qit::observable::{Observable, Pauli, PauliString};
+
+// Bell state: ⟨ZZ⟩ = ⟨XX⟩ = 1, ⟨Z0⟩ = 0
+let q = CX::new(0, 1).apply(H::new(0).apply(Qubits::zeros(2)));
+let mut obs = Observable::new();
+obs.add_term(0.5, PauliString::new(vec![(0, Pauli::Z), (1, Pauli::Z)]));
+obs.add_term(0.25, PauliString::new(vec![(0, Pauli::X), (1, Pauli::X)]));
+obs.add_term(2.0, PauliString::new(vec![(0, Pauli::Z)]));
+assert!((obs.expectation(&q) - 0.75).abs() < 1e-9);
+```
+*/
+
+use super::core::{Comp, Qubits};
+pub use super::pauli::{Pauli, PauliString};
+
+/**
+Σ c_k P_k with real coefficients c_k.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Observable {
+    pub terms: Vec<(f64, PauliString)>,
+}
+
+impl Observable {
+    pub fn new() -> Self {
+        Observable { terms: Vec::new() }
+    }
+
+    pub fn add_term(&mut self, coeff: f64, paulis: PauliString) {
+        self.terms.push((coeff, paulis));
+    }
+
+    /**
+    O|ψ⟩. The result is in general not normalised.
+     */
+    pub fn apply(&self, qubits: &Qubits) -> Qubits {
+        let mut bits = vec![Comp::zero(); qubits.bits.len()];
+        for (coeff, paulis) in self.terms.iter() {
+            let term = paulis.apply(qubits);
+            for (b, t) in bits.iter_mut().zip(term.bits.iter()) {
+                *b = *b + *t * *coeff;
+            }
+        }
+        Qubits::from_bits(qubits.size, bits)
+    }
+
+    /**
+    ⟨ψ|O|ψ⟩
+     */
+    pub fn expectation(&self, qubits: &Qubits) -> f64 {
+        let mut value = 0.0;
+        for (coeff, paulis) in self.terms.iter() {
+            value += coeff * paulis.expectation(qubits);
+        }
+        value
+    }
+}
+
+impl Default for Observable {
+    fn default() -> Self {
+        Observable::new()
+    }
+}
+
+/**
+Re⟨a|b⟩
+ */
+pub fn inner_re(a: &Qubits, b: &Qubits) -> f64 {
+    assert_eq!(a.size, b.size);
+    let mut value = 0.0;
+    for (x, y) in a.bits.iter().zip(b.bits.iter()) {
+        value += x.0 * y.0 + x.1 * y.1;
+    }
+    value
+}
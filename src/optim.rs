@@ -0,0 +1,436 @@
+/*!
+ Classical optimizers for variational algorithms.
+
+ * [`NelderMead`]: derivative free simplex method.
+ * [`SPSA`]: stochastic approximation using three evaluations per iteration, whatever the number of parameters.
+ * [`Adam`]: gradient descent with adaptive moments, for use with the gradients of the gradient module.
+
+ Every optimizer minimises a function of a parameter vector, stops after `max_iter` iterations or when
+ its convergence criterion falls below `tol` (see [`Criteria`]), and reports each iteration to a callback,
+ which can stop the optimization by returning `false`. [`Objective`] turns a parameterised circuit and an
+ observable into such a function.
+
+ All of them behave the same way around the loop: `x0` must have at least one parameter, the
+ convergence test runs before each iteration and the callback after it, so the callback sees every
+ iteration counted in [`OptimResult::iterations`] and nothing after convergence.
+
+# Example
+```
+use Qit::core::Qubits;
+use Qit::observable::{Observable, Pauli, PauliString};
+use Qit::optim::{Adam, Criteria, NelderMead, Objective};
+use Qit::params::{Angle, ParamGate, ParameterizedCircuit};
+
+// minimise ⟨Z⟩ after RY(a): the minimum -1 is at a = π
+let mut circ = ParameterizedCircuit::new(String::from("ry"));
+circ.push_param(ParamGate::RY(0, Angle::symbol("a")));
+let mut obs = Observable::new();
+obs.add_term(1.0, PauliString::new(vec![(0, Pauli::Z)]));
+let objective = Objective::new(&circ, &obs, Qubits::zeros(1));
+
+let result = NelderMead::new(0.5).minimize(
+    &mut |x| objective.cost(x),
+    &[0.1],
+    &Criteria::new(200, 1e-10),
+    &mut |_| true,
+);
+assert!((result.value + 1.0).abs() < 1e-6);
+
+let result = Adam::new(0.1).minimize(
+    &mut |x| objective.cost(x),
+    &mut |x| objective.gradient(x),
+    &[0.1],
+    &Criteria::new(1000, 1e-6),
+    &mut |_| true,
+);
+assert!((result.params[0] - std::f64::consts::PI).abs() < 1e-3);
+```
+*/
+
+use std::collections::HashMap;
+
+use rand::prelude::*;
+
+use super::core::{Applicable, Qubits};
+use super::gradient::adjoint_gradient;
+use super::observable::Observable;
+use super::params::ParameterizedCircuit;
+
+/**
+When to stop.
+
+* max_iter: maximum number of iterations.
+* tol: the optimization has converged when its criterion drops below `tol`.
+  NelderMead uses the spread of the function values over the simplex,
+  SPSA the length of the last step and Adam the norm of the gradient.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Criteria {
+    pub max_iter: usize,
+    pub tol: f64,
+}
+
+impl Criteria {
+    pub fn new(max_iter: usize, tol: f64) -> Self {
+        Criteria { max_iter, tol }
+    }
+}
+
+/**
+State passed to the callback after each iteration.
+ */
+#[derive(Clone, Debug)]
+pub struct Iteration<'a> {
+    pub iteration: usize,
+    pub params: &'a [f64],
+    pub value: f64,
+}
+
+/**
+Outcome of an optimization.
+
+`converged` is false when the optimization stopped because of `max_iter` or the callback.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct OptimResult {
+    pub params: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/**
+Nelder–Mead simplex method. The initial simplex is `x0` and `x0 + initial_step * e_i` for every axis.
+
+NaN values of the objective are treated as +∞, so such points are the first to be replaced.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct NelderMead {
+    pub initial_step: f64,
+}
+
+impl NelderMead {
+    pub fn new(initial_step: f64) -> Self {
+        NelderMead { initial_step }
+    }
+
+    pub fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64]) -> f64,
+        x0: &[f64],
+        criteria: &Criteria,
+        callback: &mut dyn FnMut(&Iteration) -> bool,
+    ) -> OptimResult {
+        assert!(!x0.is_empty(), "x0 has no parameters");
+        let n = x0.len();
+        let mut f = |x: &[f64]| {
+            let value = f(x);
+            if value.is_nan() {
+                f64::INFINITY
+            } else {
+                value
+            }
+        };
+        let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+        simplex.push((x0.to_vec(), f(x0)));
+        for i in 0..n {
+            let mut x = x0.to_vec();
+            x[i] += self.initial_step;
+            let value = f(&x);
+            simplex.push((x, value));
+        }
+
+        let mut converged = false;
+        let mut iterations = 0;
+        while iterations < criteria.max_iter {
+            simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+            if simplex[n].1 - simplex[0].1 < criteria.tol {
+                converged = true;
+                break;
+            }
+            iterations += 1;
+
+            let mut centroid = vec![0.0; n];
+            for (x, _) in simplex[..n].iter() {
+                for i in 0..n {
+                    centroid[i] += x[i] / n as f64;
+                }
+            }
+            let toward = |t: f64, x: &[f64]| -> Vec<f64> {
+                (0..n)
+                    .map(|i| centroid[i] + t * (x[i] - centroid[i]))
+                    .collect()
+            };
+
+            let reflected = toward(-1.0, &simplex[n].0);
+            let f_reflected = f(&reflected);
+            if f_reflected < simplex[0].1 {
+                let expanded = toward(-2.0, &simplex[n].0);
+                let f_expanded = f(&expanded);
+                simplex[n] = if f_expanded < f_reflected {
+                    (expanded, f_expanded)
+                } else {
+                    (reflected, f_reflected)
+                };
+            } else if f_reflected < simplex[n - 1].1 {
+                simplex[n] = (reflected, f_reflected);
+            } else {
+                let contracted = if f_reflected < simplex[n].1 {
+                    toward(-0.5, &simplex[n].0)
+                } else {
+                    toward(0.5, &simplex[n].0)
+                };
+                let f_contracted = f(&contracted);
+                if f_contracted < simplex[n].1.min(f_reflected) {
+                    simplex[n] = (contracted, f_contracted);
+                } else {
+                    // shrink toward the best point
+                    let best = simplex[0].0.clone();
+                    for (x, value) in simplex[1..].iter_mut() {
+                        for i in 0..n {
+                            x[i] = best[i] + 0.5 * (x[i] - best[i]);
+                        }
+                        *value = f(x);
+                    }
+                }
+            }
+
+            let best = simplex.iter().min_by(|a, b| a.1.total_cmp(&b.1)).unwrap();
+            let info = Iteration {
+                iteration: iterations,
+                params: &best.0,
+                value: best.1,
+            };
+            if !callback(&info) {
+                break;
+            }
+        }
+
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let (params, value) = simplex.swap_remove(0);
+        OptimResult {
+            params,
+            value,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/**
+Simultaneous perturbation stochastic approximation.
+
+At iteration k the gradient is estimated from f(x + c_k Δ) and f(x - c_k Δ) for a random Δ ∈ {±1}^n,
+and x moves by -a_k times the estimate, with a_k = a / (k + 1 + stability)^alpha and c_k = c / (k + 1)^gamma.
+A third evaluation gives f at the new x for the callback, so an iteration costs three evaluations.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct SPSA {
+    pub a: f64,
+    pub c: f64,
+    pub alpha: f64,
+    pub gamma: f64,
+    pub stability: f64,
+    pub seed: Option<u64>,
+}
+
+impl SPSA {
+    /**
+    SPSA with the usual exponents alpha = 0.602 and gamma = 0.101.
+     */
+    pub fn new(a: f64, c: f64) -> Self {
+        SPSA {
+            a,
+            c,
+            alpha: 0.602,
+            gamma: 0.101,
+            stability: 0.0,
+            seed: None,
+        }
+    }
+
+    pub fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64]) -> f64,
+        x0: &[f64],
+        criteria: &Criteria,
+        callback: &mut dyn FnMut(&Iteration) -> bool,
+    ) -> OptimResult {
+        assert!(!x0.is_empty(), "x0 has no parameters");
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        let n = x0.len();
+        let mut x = x0.to_vec();
+        let mut value = f(&x);
+        let mut step = f64::INFINITY;
+        let mut converged = false;
+        let mut iterations = 0;
+        while iterations < criteria.max_iter {
+            if step < criteria.tol {
+                converged = true;
+                break;
+            }
+            let k = iterations as f64;
+            iterations += 1;
+            let a_k = self.a / (k + 1.0 + self.stability).powf(self.alpha);
+            let c_k = self.c / (k + 1.0).powf(self.gamma);
+            let delta: Vec<f64> = (0..n)
+                .map(|_| if rng.gen::<bool>() { 1.0 } else { -1.0 })
+                .collect();
+            let plus: Vec<f64> = (0..n).map(|i| x[i] + c_k * delta[i]).collect();
+            let minus: Vec<f64> = (0..n).map(|i| x[i] - c_k * delta[i]).collect();
+            let diff = (f(&plus) - f(&minus)) / (2.0 * c_k);
+
+            let mut step_square = 0.0;
+            for i in 0..n {
+                let dx = a_k * diff / delta[i];
+                x[i] -= dx;
+                step_square += dx * dx;
+            }
+            step = step_square.sqrt();
+            value = f(&x);
+
+            let info = Iteration {
+                iteration: iterations,
+                params: &x,
+                value,
+            };
+            if !callback(&info) {
+                break;
+            }
+        }
+        OptimResult {
+            params: x,
+            value,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/**
+Adam gradient descent.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct Adam {
+    pub learning_rate: f64,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+}
+
+impl Adam {
+    /**
+    Adam with the usual beta1 = 0.9, beta2 = 0.999 and eps = 1e-8.
+     */
+    pub fn new(learning_rate: f64) -> Self {
+        Adam {
+            learning_rate,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+        }
+    }
+
+    pub fn minimize(
+        &self,
+        f: &mut dyn FnMut(&[f64]) -> f64,
+        grad: &mut dyn FnMut(&[f64]) -> Vec<f64>,
+        x0: &[f64],
+        criteria: &Criteria,
+        callback: &mut dyn FnMut(&Iteration) -> bool,
+    ) -> OptimResult {
+        assert!(!x0.is_empty(), "x0 has no parameters");
+        let n = x0.len();
+        let mut x = x0.to_vec();
+        let mut value = f(&x);
+        let mut m = vec![0.0; n];
+        let mut v = vec![0.0; n];
+        let mut converged = false;
+        let mut iterations = 0;
+        while iterations < criteria.max_iter {
+            let g = grad(&x);
+            assert_eq!(g.len(), n);
+            if g.iter().map(|gi| gi * gi).sum::<f64>().sqrt() < criteria.tol {
+                converged = true;
+                break;
+            }
+            iterations += 1;
+
+            let t = iterations as i32;
+            for i in 0..n {
+                m[i] = self.beta1 * m[i] + (1.0 - self.beta1) * g[i];
+                v[i] = self.beta2 * v[i] + (1.0 - self.beta2) * g[i] * g[i];
+                let m_hat = m[i] / (1.0 - self.beta1.powi(t));
+                let v_hat = v[i] / (1.0 - self.beta2.powi(t));
+                x[i] -= self.learning_rate * m_hat / (v_hat.sqrt() + self.eps);
+            }
+            value = f(&x);
+
+            let info = Iteration {
+                iteration: iterations,
+                params: &x,
+                value,
+            };
+            if !callback(&info) {
+                break;
+            }
+        }
+        OptimResult {
+            params: x,
+            value,
+            iterations,
+            converged,
+        }
+    }
+}
+
+/**
+Expectation value of an observable after a parameterised circuit, as a function of a parameter vector.
+
+The i-th entry of the vector is the value of the i-th symbol of [`ParameterizedCircuit::parameters`].
+ */
+pub struct Objective<'a> {
+    pub circ: &'a ParameterizedCircuit,
+    pub obs: &'a Observable,
+    pub input: Qubits,
+    pub names: Vec<String>,
+}
+
+impl<'a> Objective<'a> {
+    pub fn new(circ: &'a ParameterizedCircuit, obs: &'a Observable, input: Qubits) -> Self {
+        Objective {
+            circ,
+            obs,
+            input,
+            names: circ.parameters(),
+        }
+    }
+
+    /**
+    Map from symbol to value.
+     */
+    pub fn values(&self, params: &[f64]) -> HashMap<String, f64> {
+        assert_eq!(params.len(), self.names.len());
+        self.names
+            .iter()
+            .cloned()
+            .zip(params.iter().cloned())
+            .collect()
+    }
+
+    pub fn cost(&self, params: &[f64]) -> f64 {
+        let u = self.circ.bind(&self.values(params));
+        self.obs.expectation(&u.apply(self.input.clone()))
+    }
+
+    /**
+    Gradient of `cost` by adjoint differentiation.
+     */
+    pub fn gradient(&self, params: &[f64]) -> Vec<f64> {
+        let grad = adjoint_gradient(self.circ, self.obs, &self.input, &self.values(params));
+        self.names.iter().map(|n| grad[n]).collect()
+    }
+}
@@ -0,0 +1,225 @@
+/*!
+ Symbolic parameters for variational circuits.
+
+ A [`ParameterizedCircuit`] is a list of gates in which rotation angles may refer to named symbols.
+ Its structure is built once; [`ParameterizedCircuit::bind`] turns it into a concrete [`U`] for
+ given values of the symbols, which is all an optimizer needs to do each iteration.
+
+# Example
+```
+use std::collections::HashMap;
+use Qit::core::{Applicable, Qubits};
+use Qit::gates::{PushOps, CX, H};
+use Qit::params::{Angle, ParamGate, ParameterizedCircuit};
+
+let mut circ = ParameterizedCircuit::new(String::from("ansatz"));
+circ.push_ops(H::new(0));
+circ.push_param(ParamGate::RY(1, Angle::symbol("theta")));
+circ.push_ops(CX::new(0, 1));
+circ.push_param(ParamGate::RZ(1, Angle::scaled("theta", 2.0)));
+assert_eq!(circ.parameters(), vec![String::from("theta")]);
+
+let mut values = HashMap::new();
+for theta in [0.1, 0.2, 0.3] {
+    values.insert(String::from("theta"), theta);
+    let u = circ.bind(&values);
+    let q_out = u.apply(Qubits::zeros(2));
+    assert!((q_out._measure(&[0, 1]).iter().sum::<f64>() - 1.0).abs() < 1e-9);
+}
+```
+*/
+
+use std::collections::HashMap;
+
+use super::core::Operator;
+use super::gates::{CPhase, OperatorVec, PushOps, R, RX, RY, RZ, U, U3};
+
+/**
+Angle of a parameterised gate: `scale * symbol + offset`, or the constant `offset` when there is no symbol.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Angle {
+    pub symbol: Option<String>,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl Angle {
+    /**
+    Constant angle.
+     */
+    pub fn value(angle: f64) -> Self {
+        Angle {
+            symbol: None,
+            scale: 0.0,
+            offset: angle,
+        }
+    }
+
+    /**
+    Angle equal to the value of `name`.
+     */
+    pub fn symbol(name: &str) -> Self {
+        Angle::scaled(name, 1.0)
+    }
+
+    /**
+    Angle equal to `scale` times the value of `name`.
+     */
+    pub fn scaled(name: &str, scale: f64) -> Self {
+        Angle {
+            symbol: Some(String::from(name)),
+            scale,
+            offset: 0.0,
+        }
+    }
+
+    /**
+    Evaluate the angle. Panics if the symbol is missing from `values`.
+     */
+    pub fn resolve(&self, values: &HashMap<String, f64>) -> f64 {
+        match &self.symbol {
+            None => self.offset,
+            Some(name) => match values.get(name) {
+                Some(v) => self.scale * v + self.offset,
+                None => panic!("unbound parameter: {}", name),
+            },
+        }
+    }
+}
+
+/**
+Gate whose angles are [`Angle`]s. The fields follow the constructors of the gates module.
+
+* R(target, angle), RX(target, angle), RY(target, angle), RZ(target, angle)
+* CPhase(control, target, angle)
+* U3(target, theta, phi, lambda)
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamGate {
+    R(usize, Angle),
+    RX(usize, Angle),
+    RY(usize, Angle),
+    RZ(usize, Angle),
+    CPhase(usize, usize, Angle),
+    U3(usize, Angle, Angle, Angle),
+}
+
+impl ParamGate {
+    /**
+    The angles of the gate, in the order of its constructor.
+     */
+    pub fn angles(&self) -> Vec<&Angle> {
+        match self {
+            ParamGate::R(_, a)
+            | ParamGate::RX(_, a)
+            | ParamGate::RY(_, a)
+            | ParamGate::RZ(_, a)
+            | ParamGate::CPhase(_, _, a) => vec![a],
+            ParamGate::U3(_, theta, phi, lambda) => vec![theta, phi, lambda],
+        }
+    }
+
+    /**
+    Mutable references to the angles of the gate, in the same order as [`ParamGate::angles`].
+     */
+    pub fn angles_mut(&mut self) -> Vec<&mut Angle> {
+        match self {
+            ParamGate::R(_, a)
+            | ParamGate::RX(_, a)
+            | ParamGate::RY(_, a)
+            | ParamGate::RZ(_, a)
+            | ParamGate::CPhase(_, _, a) => vec![a],
+            ParamGate::U3(_, theta, phi, lambda) => vec![theta, phi, lambda],
+        }
+    }
+
+    /**
+    The concrete gate for the given values of the symbols.
+     */
+    pub fn bind(&self, values: &HashMap<String, f64>) -> Box<dyn Operator> {
+        match self {
+            ParamGate::R(t, a) => Box::new(R::new(*t, a.resolve(values))),
+            ParamGate::RX(t, a) => Box::new(RX::new(*t, a.resolve(values))),
+            ParamGate::RY(t, a) => Box::new(RY::new(*t, a.resolve(values))),
+            ParamGate::RZ(t, a) => Box::new(RZ::new(*t, a.resolve(values))),
+            ParamGate::CPhase(c, t, a) => Box::new(CPhase::new(*c, *t, a.resolve(values))),
+            ParamGate::U3(t, theta, phi, lambda) => Box::new(U3::new(
+                *t,
+                theta.resolve(values),
+                phi.resolve(values),
+                lambda.resolve(values),
+            )),
+        }
+    }
+}
+
+/**
+Element of a [`ParameterizedCircuit`].
+ */
+#[derive(Clone)]
+pub enum Element {
+    Fixed(Box<dyn Operator>),
+    Param(ParamGate),
+}
+
+/**
+Circuit built from fixed gates and parameterised gates.
+ */
+#[derive(Clone)]
+pub struct ParameterizedCircuit {
+    pub elements: Vec<Element>,
+    pub label: String,
+}
+
+impl ParameterizedCircuit {
+    pub fn new(label: String) -> Self {
+        ParameterizedCircuit {
+            elements: Vec::new(),
+            label,
+        }
+    }
+
+    pub fn push_param(&mut self, gate: ParamGate) {
+        self.elements.push(Element::Param(gate));
+    }
+
+    /**
+    Names of all symbols used in the circuit, sorted and without duplicates.
+     */
+    pub fn parameters(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for element in self.elements.iter() {
+            if let Element::Param(gate) = element {
+                for angle in gate.angles() {
+                    if let Some(name) = &angle.symbol {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /**
+    Concrete circuit for the given values of the symbols. Panics if a symbol is missing from `values`.
+     */
+    pub fn bind(&self, values: &HashMap<String, f64>) -> U {
+        let mut gates: OperatorVec = Vec::with_capacity(self.elements.len());
+        for element in self.elements.iter() {
+            match element {
+                Element::Fixed(op) => gates.push(op.clone()),
+                Element::Param(gate) => gates.push(gate.bind(values)),
+            }
+        }
+        U::new(gates, self.label.clone())
+    }
+}
+
+impl PushOps for ParameterizedCircuit {
+    fn push_ops(&mut self, op: impl Operator + 'static) {
+        self.elements.push(Element::Fixed(Box::new(op)));
+    }
+}
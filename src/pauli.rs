@@ -0,0 +1,138 @@
+/*!
+ Pauli matrices and Pauli strings, shared by the gates, observable and stabilizer modules.
+
+ The matrices follow the textbook convention, in particular Y = i(|1⟩⟨0| - |0⟩⟨1|).
+ (Note that [`gates::Y`](crate::gates::Y) is -Y.)
+
+# Example
+```
+use Qit::core::Qubits;
+use Qit::pauli::{Pauli, PauliString};
+
+// X0 Z1 |10⟩ = -|11⟩
+let p = PauliString::new(vec![(0, Pauli::X), (1, Pauli::Z)]);
+let q_out = p.apply(&Qubits::from_num(2, 0b10));
+assert_eq!(q_out.bits[0b11].0, -1.0);
+assert_eq!(format!("{}", p), "X0 Z1");
+```
+*/
+
+use std::fmt;
+
+use super::core::{Comp, Matrix2, Qubits};
+
+/**
+Single-qubit Pauli matrix.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pauli {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+impl Pauli {
+    /**
+    The 2x2 matrix, with Y = i(|1⟩⟨0| - |0⟩⟨1|).
+     */
+    pub fn matrix(&self) -> Matrix2 {
+        let (o, l, i) = (Comp::zero(), Comp(1.0, 0.0), Comp(0.0, 1.0));
+        match self {
+            Pauli::I => [[l, o], [o, l]],
+            Pauli::X => [[o, l], [l, o]],
+            Pauli::Y => [[o, i * -1.0], [i, o]],
+            Pauli::Z => [[l, o], [o, l * -1.0]],
+        }
+    }
+}
+
+/**
+Tensor product of Pauli matrices on the listed qubits. Qubits not listed carry the identity.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct PauliString {
+    pub paulis: Vec<(usize, Pauli)>,
+}
+
+impl PauliString {
+    pub fn new(paulis: Vec<(usize, Pauli)>) -> Self {
+        for i in 0..paulis.len() {
+            for j in 0..i {
+                assert_ne!(
+                    paulis[i].0, paulis[j].0,
+                    "qubit appears twice in a Pauli string"
+                );
+            }
+        }
+        PauliString { paulis }
+    }
+
+    /**
+    Mask of the qubits on which the string flips bits (X or Y).
+     */
+    pub fn flip_mask(&self) -> usize {
+        let mut mask = 0;
+        for (q, p) in self.paulis.iter() {
+            if *p == Pauli::X || *p == Pauli::Y {
+                mask |= 1 << q;
+            }
+        }
+        mask
+    }
+
+    /**
+    Phase picked up by the basis state |index⟩, so that P|index⟩ = phase|index ^ flip_mask⟩.
+     */
+    pub fn phase(&self, index: usize) -> Comp {
+        let mut phase = Comp(1.0, 0.0);
+        for (q, p) in self.paulis.iter() {
+            let bit = (index >> q) & 1;
+            phase = match (p, bit) {
+                (Pauli::Z, 1) => phase * -1.0,
+                (Pauli::Y, 0) => phase * Comp(0.0, 1.0),
+                (Pauli::Y, _) => phase * Comp(0.0, -1.0),
+                _ => phase,
+            };
+        }
+        phase
+    }
+
+    /**
+    P|ψ⟩
+     */
+    pub fn apply(&self, qubits: &Qubits) -> Qubits {
+        let flip = self.flip_mask();
+        let mut bits = vec![Comp::zero(); qubits.bits.len()];
+        for (index, amp) in qubits.bits.iter().enumerate() {
+            bits[index ^ flip] = *amp * self.phase(index);
+        }
+        Qubits::from_bits(qubits.size, bits)
+    }
+
+    /**
+    ⟨ψ|P|ψ⟩, which is real because P is Hermitian.
+     */
+    pub fn expectation(&self, qubits: &Qubits) -> f64 {
+        let applied = self.apply(qubits);
+        let mut value = 0.0;
+        for (x, y) in qubits.bits.iter().zip(applied.bits.iter()) {
+            value += x.0 * y.0 + x.1 * y.1;
+        }
+        value
+    }
+}
+
+/**
+Written as e.g. "Z0 Z3 X5".
+ */
+impl fmt::Display for PauliString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let terms: Vec<String> = self
+            .paulis
+            .iter()
+            .map(|(q, p)| format!("{:?}{}", p, q))
+            .collect();
+        write!(f, "{}", terms.join(" "))
+    }
+}
@@ -0,0 +1,244 @@
+/*!
+ Sparse state vector.
+
+ Arithmetic circuits applied to a few basis states keep only a handful of nonzero amplitudes.
+ [`SparseQubits`] stores just those amplitudes in a hash map keyed by the basis index,
+ so memory and time scale with the number of nonzero amplitudes instead of 2^n.
+
+ Gates are applied through [`Applicable::lower`], so every struct of the gates module
+ (including U and CU) can be used unchanged. Amplitudes whose magnitude drops below
+ `threshold` are pruned after each step.
+
+# Example
+```
+use Qit::circuits::wrapping_qadd_const;
+use Qit::core::QuantumState;
+use Qit::gates::H;
+use Qit::sparse::SparseQubits;
+
+// 50 qubits: far too many for Qubits
+let b: Vec<usize> = (0..50).collect();
+let mut q = SparseQubits::from_num(50, 5);
+q.apply(&H::new(49)).unwrap();
+q.apply(&wrapping_qadd_const(&b, 3)).unwrap();
+assert_eq!(q.len(), 2);
+assert!((q.amplitude(8).0 - 0.5_f64.sqrt()).abs() < 1e-9);
+assert!((q.amplitude(8 | 1 << 49).0 - 0.5_f64.sqrt()).abs() < 1e-9);
+```
+*/
+
+use std::collections::HashMap;
+
+use super::core::{
+    Applicable, Comp, ElementarySink, Matrix2, QuantumState, Qubits, UnsupportedGate,
+};
+
+/**
+Default magnitude under which amplitudes are dropped.
+*/
+pub const DEFAULT_THRESHOLD: f64 = 1e-12;
+
+/**
+Qubits represented as a map from basis index to amplitude.
+
+Indices missing from `amps` have amplitude zero.
+*/
+#[derive(Clone)]
+pub struct SparseQubits {
+    pub size: usize,
+    pub amps: HashMap<usize, Comp>,
+    pub threshold: f64,
+}
+
+impl SparseQubits {
+    /**
+    Output |0...0⟩ Qubit of input size
+    */
+    pub fn zeros(size: usize) -> Self {
+        SparseQubits::from_num(size, 0)
+    }
+
+    /**
+    1.0 * |number⟩
+    */
+    pub fn from_num(size: usize, number: usize) -> Self {
+        assert!(size <= usize::BITS as usize);
+        assert!(size == usize::BITS as usize || (number >> size) == 0);
+        let mut amps = HashMap::new();
+        amps.insert(number, Comp(1.0, 0.0));
+        SparseQubits {
+            size,
+            amps,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /**
+    Convert dense qubits, keeping only the amplitudes above the default threshold.
+    */
+    pub fn from_qubits(qubits: &Qubits) -> Self {
+        let mut amps = HashMap::new();
+        for (index, amp) in qubits.bits.iter().enumerate() {
+            if is_significant(*amp, DEFAULT_THRESHOLD) {
+                amps.insert(index, *amp);
+            }
+        }
+        SparseQubits {
+            size: qubits.size,
+            amps,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+
+    /**
+    Convert to dense qubits. Requires 2^size amplitudes of memory.
+    */
+    pub fn to_qubits(&self) -> Qubits {
+        let mut bits = vec![Comp::zero(); 1 << self.size];
+        for (index, amp) in self.amps.iter() {
+            bits[*index] = *amp;
+        }
+        Qubits::from_bits(self.size, bits)
+    }
+
+    pub fn amplitude(&self, index: usize) -> Comp {
+        *self.amps.get(&index).unwrap_or(&Comp::zero())
+    }
+
+    /**
+    Number of stored (nonzero) amplitudes
+    */
+    pub fn len(&self) -> usize {
+        self.amps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.amps.is_empty()
+    }
+
+    /**
+    Drop every amplitude whose magnitude is below `threshold`.
+    */
+    pub fn prune(&mut self) {
+        let threshold = self.threshold;
+        self.amps.retain(|_, amp| is_significant(*amp, threshold));
+    }
+
+    /**
+    Function to obtain the most probable qubit string
+    */
+    pub fn pop_most_plausible(&self) -> usize {
+        let mut max_prob = 0.0;
+        let mut max_idx = 0;
+        for (index, amp) in self.amps.iter() {
+            let prob = amp.abs_square();
+            if max_prob < prob {
+                max_prob = prob;
+                max_idx = *index;
+            }
+        }
+        max_idx
+    }
+
+    /**
+    Output the stored bit strings and their complex numbers in ascending order
+    */
+    pub fn print_cmps(&self) {
+        let mut indices: Vec<&usize> = self.amps.keys().collect();
+        indices.sort();
+        for index in indices {
+            println!(
+                "|{index:0>size$b}⟩ : {cmp}",
+                index = index,
+                size = self.size,
+                cmp = self.amps[index]
+            );
+        }
+    }
+}
+
+impl ElementarySink for SparseQubits {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        assert!(target < self.size);
+        let step = 1 << target;
+        let mut mask = 0;
+        for c in controls {
+            assert!(*c < self.size);
+            mask |= 1 << c;
+        }
+        assert!(mask & step == 0, "target bit is also a control bit");
+
+        let mut amps = HashMap::with_capacity(self.amps.len());
+        for (index, amp) in self.amps.iter() {
+            if index & mask != mask {
+                amps.insert(*index, *amp);
+                continue;
+            }
+            let idx0 = index & !step;
+            let idx1 = idx0 | step;
+            // each pair is handled once, from |..0..⟩ when it is stored
+            if *index == idx1 && self.amps.contains_key(&idx0) {
+                continue;
+            }
+            let a0 = self.amplitude(idx0);
+            let a1 = self.amplitude(idx1);
+            let b0 = matrix[0][0] * a0 + matrix[0][1] * a1;
+            let b1 = matrix[1][0] * a0 + matrix[1][1] * a1;
+            if is_significant(b0, self.threshold) {
+                amps.insert(idx0, b0);
+            }
+            if is_significant(b1, self.threshold) {
+                amps.insert(idx1, b1);
+            }
+        }
+        self.amps = amps;
+        Ok(())
+    }
+}
+
+impl QuantumState for SparseQubits {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let mut state = self.clone();
+        op.lower(&mut state, &[])?;
+        *self = state;
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        use rand::prelude::*;
+        let p1 = self.marginal_probs(&[target])[1];
+        let outcome = rand::thread_rng().gen::<f64>() < p1;
+        let norm = 1.0 / (if outcome { p1 } else { 1.0 - p1 }).sqrt();
+        self.amps
+            .retain(|index, _| ((index >> target) & 1 == 1) == outcome);
+        for amp in self.amps.values_mut() {
+            *amp = *amp * norm;
+        }
+        outcome
+    }
+
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        let mut probs = vec![0.0; 1 << tar.len()];
+        for (index, amp) in self.amps.iter() {
+            let mut tar_idx = 0;
+            for (j, t) in tar.iter().enumerate() {
+                tar_idx |= (1 & (index >> t)) << j;
+            }
+            probs[tar_idx] += amp.abs_square();
+        }
+        probs
+    }
+}
+
+fn is_significant(amp: Comp, threshold: f64) -> bool {
+    amp.abs_square() > threshold * threshold
+}
@@ -0,0 +1,436 @@
+/*!
+ Stabilizer (Clifford) simulator.
+
+ Circuits made only of Clifford gates (H, S, X, Y, Z, CX, ...) can be simulated in polynomial time
+ by tracking the stabilizer tableau of the state (Aaronson and Gottesman, "Improved simulation of
+ stabilizer circuits", 2004). This allows error-correction experiments with hundreds of qubits.
+
+ Gates are read through [`Applicable::lower`], so U and CU composed of Clifford gates are accepted.
+ A gate is accepted when its matrix is Clifford up to global phase, e.g. `R::new(q, PI / 2.0)` is S.
+ Non-Clifford operations such as `R::new(q, PI / 4.0)` or CCX are reported as [`UnsupportedGate`].
+
+# Example
+```
+use Qit::core::QuantumState;
+use Qit::gates::{CX, H, OperatorVec, PushOps, U};
+use Qit::stabilizer::StabilizerState;
+
+// 300-qubit GHZ state
+let mut circ = OperatorVec::new();
+circ.push_ops(H::new(0));
+for i in 1..300 {
+    circ.push_ops(CX::new(i - 1, i));
+}
+let ghz = U::new(circ, String::from("ghz"));
+
+let mut state = StabilizerState::new(300);
+state.apply(&ghz).unwrap();
+let first = state.measure(0);
+for i in 1..300 {
+    assert_eq!(state.measure(i), first);
+}
+```
+*/
+
+use rand::prelude::*;
+
+use super::core::{Applicable, Comp, ElementarySink, Matrix2, QuantumState, UnsupportedGate};
+use super::pauli::Pauli;
+
+const EPS: f64 = 1e-9;
+
+/**
+Stabilizer tableau of an n-qubit state.
+
+Rows 0..n are destabilizers and rows n..2n are stabilizers. Each row is a Pauli string with
+sign (-1)^r, where bit pair (x, z) on a qubit means I (0, 0), X (1, 0), Y (1, 1) or Z (0, 1).
+*/
+#[derive(Clone)]
+pub struct StabilizerState {
+    pub size: usize,
+    x: Vec<Vec<bool>>,
+    z: Vec<Vec<bool>>,
+    r: Vec<bool>,
+}
+
+impl StabilizerState {
+    /**
+    Tableau of |0...0⟩
+    */
+    pub fn new(size: usize) -> Self {
+        let mut x = vec![vec![false; size]; 2 * size];
+        let mut z = vec![vec![false; size]; 2 * size];
+        for i in 0..size {
+            x[i][i] = true;
+            z[i + size][i] = true;
+        }
+        StabilizerState {
+            size,
+            x,
+            z,
+            r: vec![false; 2 * size],
+        }
+    }
+
+    /**
+    Measure `target` drawing the random outcome (if any) from `rng`.
+    */
+    pub fn measure_with(&mut self, target: usize, rng: &mut impl Rng) -> bool {
+        let random: bool = rng.gen();
+        self.collapse(target, random).0
+    }
+
+    /**
+    Measure `target`, taking `random` as the outcome when it is not deterministic.
+    Returns the outcome and its probability.
+    */
+    fn collapse(&mut self, target: usize, random: bool) -> (bool, f64) {
+        assert!(target < self.size);
+        let n = self.size;
+        let p = (n..2 * n).find(|&i| self.x[i][target]);
+        match p {
+            Some(p) => {
+                for i in 0..2 * n {
+                    if i != p && self.x[i][target] {
+                        self.rowsum(i, p);
+                    }
+                }
+                self.x[p - n] = self.x[p].clone();
+                self.z[p - n] = self.z[p].clone();
+                self.r[p - n] = self.r[p];
+                self.x[p] = vec![false; n];
+                self.z[p] = vec![false; n];
+                self.z[p][target] = true;
+                self.r[p] = random;
+                (random, 0.5)
+            }
+            None => (self.deterministic_outcome(target).unwrap(), 1.0),
+        }
+    }
+
+    /**
+    Outcome of measuring `target` if it is deterministic, `None` if it is uniformly random.
+    The state is not changed.
+    */
+    pub fn deterministic_outcome(&self, target: usize) -> Option<bool> {
+        assert!(target < self.size);
+        let n = self.size;
+        if (n..2 * n).any(|i| self.x[i][target]) {
+            return None;
+        }
+        let (mut x, mut z, mut r) = (vec![false; n], vec![false; n], false);
+        for i in 0..n {
+            if self.x[i][target] {
+                r = row_product(
+                    &mut x,
+                    &mut z,
+                    r,
+                    &self.x[i + n],
+                    &self.z[i + n],
+                    self.r[i + n],
+                );
+            }
+        }
+        Some(r)
+    }
+
+    /**
+    Sample `shots` measurements of the qubits in `tar` without changing the state.
+    Bit j of each result is the outcome of `tar[j]`, as in [`Qubits::_measure`](crate::core::Qubits::_measure).
+    */
+    pub fn sample(&self, tar: &[usize], shots: usize) -> Vec<usize> {
+        self.sample_with(tar, shots, &mut rand::thread_rng())
+    }
+
+    /**
+    [`StabilizerState::sample`] drawing the random outcomes from `rng`.
+    */
+    pub fn sample_with(&self, tar: &[usize], shots: usize, rng: &mut impl Rng) -> Vec<usize> {
+        assert!(tar.len() <= usize::BITS as usize);
+        let mut results = Vec::with_capacity(shots);
+        for _ in 0..shots {
+            let mut state = self.clone();
+            let mut result = 0;
+            for (j, t) in tar.iter().enumerate() {
+                if state.measure_with(*t, rng) {
+                    result |= 1 << j;
+                }
+            }
+            results.push(result);
+        }
+        results
+    }
+
+    /**
+    Stabilizer generators as strings such as "+XXI", with qubit 0 leftmost.
+    */
+    pub fn stabilizers(&self) -> Vec<String> {
+        let n = self.size;
+        let mut strs = Vec::new();
+        for i in n..2 * n {
+            let mut s = String::from(if self.r[i] { "-" } else { "+" });
+            for j in 0..n {
+                s.push(match (self.x[i][j], self.z[i][j]) {
+                    (false, false) => 'I',
+                    (true, false) => 'X',
+                    (true, true) => 'Y',
+                    (false, true) => 'Z',
+                });
+            }
+            strs.push(s);
+        }
+        strs
+    }
+
+    fn rowsum(&mut self, h: usize, i: usize) {
+        let (xi, zi, ri) = (self.x[i].clone(), self.z[i].clone(), self.r[i]);
+        let rh = self.r[h];
+        self.r[h] = row_product(&mut self.x[h], &mut self.z[h], rh, &xi, &zi, ri);
+    }
+
+    fn cx(&mut self, a: usize, b: usize) {
+        for i in 0..2 * self.size {
+            let (xa, za, xb, zb) = (self.x[i][a], self.z[i][a], self.x[i][b], self.z[i][b]);
+            self.r[i] ^= xa & zb & !(xb ^ za);
+            self.x[i][b] = xb ^ xa;
+            self.z[i][a] = za ^ zb;
+        }
+    }
+
+    /**
+    Conjugate every row on qubit `q` by the single-qubit Clifford whose images of X, Y, Z are given.
+    */
+    fn conjugate(&mut self, q: usize, images: &[(bool, Pauli); 3]) {
+        for i in 0..2 * self.size {
+            let image = match (self.x[i][q], self.z[i][q]) {
+                (false, false) => continue,
+                (true, false) => images[0],
+                (true, true) => images[1],
+                (false, true) => images[2],
+            };
+            let (x, z) = xz_bits(image.1);
+            self.x[i][q] = x;
+            self.z[i][q] = z;
+            self.r[i] ^= image.0;
+        }
+    }
+
+    fn single_clifford(&mut self, q: usize, matrix: &Matrix2) -> Result<(), String> {
+        let mut images = [(false, Pauli::I); 3];
+        for (k, p) in [Pauli::X, Pauli::Y, Pauli::Z].iter().enumerate() {
+            let image = mul(&mul(matrix, &p.matrix()), &dagger(matrix));
+            images[k] = match signed_pauli(&image) {
+                Some(image) => image,
+                None => return Err(String::from("not a Clifford operation")),
+            };
+        }
+        self.conjugate(q, &images);
+        Ok(())
+    }
+
+    fn phase_power(&mut self, q: usize, k: usize) {
+        let s = [
+            [Comp(1.0, 0.0), Comp::zero()],
+            [Comp::zero(), Comp(0.0, 1.0)],
+        ];
+        for _ in 0..(k % 4) {
+            self.single_clifford(q, &s).unwrap();
+        }
+    }
+}
+
+impl QuantumState for StabilizerState {
+    fn num_qubits(&self) -> usize {
+        self.size
+    }
+
+    /**
+    The gates are applied to a copy of the tableau, so a non-Clifford gate anywhere in `op` leaves
+    the state unchanged.
+    */
+    fn apply(&mut self, op: &dyn Applicable) -> Result<(), UnsupportedGate> {
+        let mut state = self.clone();
+        op.lower(&mut state, &[])?;
+        *self = state;
+        Ok(())
+    }
+
+    fn measure(&mut self, target: usize) -> bool {
+        self.measure_with(target, &mut rand::thread_rng())
+    }
+
+    /**
+    Computed by measuring the targets in turn for every outcome, so the cost grows as 2^tar.len().
+    */
+    fn marginal_probs(&self, tar: &[usize]) -> Vec<f64> {
+        let mut probs = vec![0.0; 1 << tar.len()];
+        for (tar_idx, prob) in probs.iter_mut().enumerate() {
+            let mut state = self.clone();
+            *prob = 1.0;
+            for (j, t) in tar.iter().enumerate() {
+                let wanted = (tar_idx >> j) & 1 == 1;
+                let (outcome, p) = state.collapse(*t, wanted);
+                if outcome != wanted {
+                    *prob = 0.0;
+                    break;
+                }
+                *prob *= p;
+            }
+        }
+        probs
+    }
+}
+
+impl ElementarySink for StabilizerState {
+    fn apply_controlled(
+        &mut self,
+        target: usize,
+        matrix: &Matrix2,
+        controls: &[usize],
+    ) -> Result<(), String> {
+        assert!(target < self.size);
+        if controls.is_empty() {
+            return self.single_clifford(target, matrix);
+        }
+        // matrix = i^k P
+        let decomposed = [Pauli::I, Pauli::X, Pauli::Y, Pauli::Z]
+            .iter()
+            .flat_map(|p| (0..4).map(move |k| (*p, k)))
+            .find(|(p, k)| is_close(matrix, &scale(&p.matrix(), i_power(*k))));
+        let (pauli, k) = match decomposed {
+            Some(d) => d,
+            None => return Err(String::from("controlled operation is not a Pauli")),
+        };
+        if pauli == Pauli::I && k == 0 {
+            return Ok(());
+        }
+        if controls.len() > 1 {
+            return Err(String::from("multi-controlled operation is not Clifford"));
+        }
+        let c = controls[0];
+        assert!(c < self.size && c != target);
+        let h = [
+            [Comp(1.0, 0.0), Comp(1.0, 0.0)],
+            [Comp(1.0, 0.0), Comp(-1.0, 0.0)],
+        ];
+        let h = scale(&h, Comp(std::f64::consts::FRAC_1_SQRT_2, 0.0));
+        match pauli {
+            Pauli::I => {}
+            Pauli::X => self.cx(c, target),
+            Pauli::Y => {
+                self.phase_power(target, 3);
+                self.cx(c, target);
+                self.phase_power(target, 1);
+            }
+            Pauli::Z => {
+                self.single_clifford(target, &h)?;
+                self.cx(c, target);
+                self.single_clifford(target, &h)?;
+            }
+        }
+        // controlled global phase i^k is S^k on the control
+        self.phase_power(c, k);
+        Ok(())
+    }
+}
+
+/**
+Multiply the row (x, z, r) by (xi, zi, ri) in place and return the new sign bit.
+*/
+fn row_product(
+    x: &mut [bool],
+    z: &mut [bool],
+    r: bool,
+    xi: &[bool],
+    zi: &[bool],
+    ri: bool,
+) -> bool {
+    let mut phase: i64 = 2 * (r as i64) + 2 * (ri as i64);
+    for j in 0..x.len() {
+        phase += g(xi[j], zi[j], x[j], z[j]);
+        x[j] ^= xi[j];
+        z[j] ^= zi[j];
+    }
+    phase.rem_euclid(4) == 2
+}
+
+/**
+Exponent of i obtained when multiplying the Paulis (x1, z1) and (x2, z2).
+*/
+fn g(x1: bool, z1: bool, x2: bool, z2: bool) -> i64 {
+    let (x2, z2) = (x2 as i64, z2 as i64);
+    match (x1, z1) {
+        (false, false) => 0,
+        (true, true) => z2 - x2,
+        (true, false) => z2 * (2 * x2 - 1),
+        (false, true) => x2 * (1 - 2 * z2),
+    }
+}
+
+/**
+Tableau bits (x, z) of a Pauli matrix.
+*/
+fn xz_bits(p: Pauli) -> (bool, bool) {
+    match p {
+        Pauli::I => (false, false),
+        Pauli::X => (true, false),
+        Pauli::Y => (true, true),
+        Pauli::Z => (false, true),
+    }
+}
+
+fn signed_pauli(m: &Matrix2) -> Option<(bool, Pauli)> {
+    for p in [Pauli::X, Pauli::Y, Pauli::Z] {
+        if is_close(m, &p.matrix()) {
+            return Some((false, p));
+        }
+        if is_close(m, &scale(&p.matrix(), Comp(-1.0, 0.0))) {
+            return Some((true, p));
+        }
+    }
+    None
+}
+
+fn i_power(k: usize) -> Comp {
+    [
+        Comp(1.0, 0.0),
+        Comp(0.0, 1.0),
+        Comp(-1.0, 0.0),
+        Comp(0.0, -1.0),
+    ][k % 4]
+}
+
+fn mul(a: &Matrix2, b: &Matrix2) -> Matrix2 {
+    let mut m = [[Comp::zero(); 2]; 2];
+    for i in 0..2 {
+        for j in 0..2 {
+            m[i][j] = a[i][0] * b[0][j] + a[i][1] * b[1][j];
+        }
+    }
+    m
+}
+
+fn dagger(a: &Matrix2) -> Matrix2 {
+    let mut m = [[Comp::zero(); 2]; 2];
+    for i in 0..2 {
+        for j in 0..2 {
+            m[i][j] = Comp(a[j][i].0, -a[j][i].1);
+        }
+    }
+    m
+}
+
+fn scale(a: &Matrix2, c: Comp) -> Matrix2 {
+    [[a[0][0] * c, a[0][1] * c], [a[1][0] * c, a[1][1] * c]]
+}
+
+fn is_close(a: &Matrix2, b: &Matrix2) -> bool {
+    for i in 0..2 {
+        for j in 0..2 {
+            if (a[i][j] - b[i][j]).abs_square() > EPS {
+                return false;
+            }
+        }
+    }
+    true
+}
@@ -1,6 +1,6 @@
 use std::f64::consts::PI;
 
-use super::core::{Applicable, Comp, Operator, Qubits};
+use super::core::{Applicable, Comp, Inversible, Operator, OperatorClone, QuantumState, Qubits};
 
 #[test]
 fn test_complex() {
@@ -19,7 +19,7 @@ fn test_complex() {
 }
 
 fn zero() -> Qubits {
-    return Qubits::zeros(2);
+    Qubits::zeros(2)
 }
 
 #[test]
@@ -104,17 +104,17 @@ fn test_half_adder() {
     for num in 0..4 {
         let q_in = Qubits::from_num(4, num);
         let q_out = &u.apply(q_in);
-        let add = ((num >> 1) & 1) + ((num >> 0) & 1);
+        let add = ((num >> 1) & 1) + (num & 1);
         let num = num | (add << 2);
         let q_expected = Qubits::from_num(4, num);
-        isequal_qubits(&q_out, &q_expected);
+        isequal_qubits(q_out, &q_expected);
     }
 }
 
 #[test]
 fn test_full_adder() {
     use super::circuits::full_adder_nbits;
-    let u = full_adder_nbits(&vec![3, 4, 5], &vec![0, 1, 2], &vec![6, 7, 8]);
+    let u = full_adder_nbits(&[3, 4, 5], &[0, 1, 2], &[6, 7, 8]);
     for num in 0..64 {
         let q_in = Qubits::from_num(9, num);
         let q_out = u.apply(q_in);
@@ -129,11 +129,7 @@ fn test_full_adder() {
 #[test]
 fn test_full_adder10() {
     use super::circuits::full_adder_nbits;
-    let u = full_adder_nbits(
-        &vec![5, 6, 7, 8, 9],
-        &vec![0, 1, 2, 3, 4],
-        &vec![10, 11, 12, 13, 14],
-    );
+    let u = full_adder_nbits(&[5, 6, 7, 8, 9], &[0, 1, 2, 3, 4], &[10, 11, 12, 13, 14]);
     for num in 0..1024 {
         let q_in = Qubits::from_num(15, num);
         let q_out = u.apply(q_in);
@@ -149,7 +145,7 @@ fn test_full_adder10() {
 #[test]
 fn test_full_sub() {
     use super::circuits::substract_nbits;
-    let u = substract_nbits(&vec![3, 4, 5], &vec![0, 1, 2], &vec![6, 7, 8]);
+    let u = substract_nbits(&[3, 4, 5], &[0, 1, 2], &[6, 7, 8]);
     println!("{}", u.name());
     for num in 0..64 {
         let q_in = Qubits::from_num(9, num);
@@ -167,7 +163,7 @@ fn test_full_sub() {
 #[test]
 fn test_swap() {
     use super::circuits::swap;
-    let u = swap(&vec![0, 1, 2], &vec![3, 4, 5]);
+    let u = swap(&[0, 1, 2], &[3, 4, 5]);
     println!("{}", u.name());
     for num in 0..64 {
         let q_in = Qubits::from_num(6, num);
@@ -185,10 +181,10 @@ fn test_swap() {
 fn test_moduler_adder() {
     use super::circuits::mod_add;
     let u = mod_add(
-        &vec![0, 1, 2, 3],
-        &vec![4, 5, 6, 7],
-        &vec![8, 9, 10, 11],
-        &vec![12, 13, 14, 15],
+        &[0, 1, 2, 3],
+        &[4, 5, 6, 7],
+        &[8, 9, 10, 11],
+        &[12, 13, 14, 15],
         16,
         7,
     );
@@ -213,9 +209,9 @@ fn test_add_const() {
     use super::circuits::{overflow_qadd_const, wrapping_qadd_const};
     // let u = add_const(vec![0, 1, 2, 3])
     for a in 0..8 {
-        let u = add_const(&vec![0, 1, 2, 3], a);
-        let u1 = wrapping_qadd_const(&vec![0, 1, 2, 3], a);
-        let u2 = overflow_qadd_const(&vec![0, 1, 2], 3, a);
+        let u = add_const(&[0, 1, 2, 3], a);
+        let u1 = wrapping_qadd_const(&[0, 1, 2, 3], a);
+        let u2 = overflow_qadd_const(&[0, 1, 2], 3, a);
         println!("u1:{}", u1.name());
         println!("u2:{}", u2.name());
         for b in 0..8 {
@@ -241,7 +237,7 @@ fn test_sub_const() {
     use super::circuits::sub_const;
     // let u = add_const(vec![0, 1, 2, 3])
     for a in 0..8 {
-        let u = sub_const(&vec![0, 1, 2, 3], a);
+        let u = sub_const(&[0, 1, 2, 3], a);
         for b in 0..8 {
             let q_in = Qubits::from_num(4, b);
             // let b_out = (a + b);
@@ -265,7 +261,7 @@ fn test_mod_add_const() {
     // let u = add_const(vec![0, 1, 2, 3])
     let n = 7;
     for a in 0..8 {
-        let u = mod_add_const(&vec![0, 1, 2], 3, a, n);
+        let u = mod_add_const(&[0, 1, 2], 3, a, n);
         for b in 0..6 {
             let q_in = Qubits::from_num(5, b);
             // let b_out = (a + b);
@@ -292,7 +288,7 @@ fn test_add_const_2_power() {
     use super::circuits::add_const_2_power;
 
     for a in 0..4 {
-        let u = add_const_2_power(&vec![0, 1, 2, 3, 4], a);
+        let u = add_const_2_power(&[0, 1, 2, 3, 4], a);
         println!("{}", u.name());
         for b in 0..16 {
             let q_in = Qubits::from_num(5, b);
@@ -310,7 +306,7 @@ fn test_cmm_const() {
     use super::circuits::cmm_const;
     let n = 15;
     for a in 0..n {
-        let u = cmm_const(&vec![0, 1, 2, 3], &vec![4, 5, 6, 7], 8, 9, a, n);
+        let u = cmm_const(&[0, 1, 2, 3], &[4, 5, 6, 7], 8, 9, a, n);
         for b in 0..n {
             let q_in = Qubits::from_num(10, b | 1 << 9);
             let q_out = u.apply(q_in);
@@ -330,22 +326,15 @@ fn test_cmm_const() {
 
 #[test]
 fn test_me_const() {
-    use super::circuits::{cmm_const, me_const, swap};
-    use super::core::mod_funcs::{is_coprime, mod_inv, mod_power};
-    use super::gates::X;
+    use super::circuits::me_const;
+    use super::core::mod_funcs::{is_coprime, mod_power};
+
     let n = 15;
     for a in 2..n {
         if !is_coprime(a, n) {
             continue;
         }
-        let u = me_const(
-            &vec![0, 1, 2, 3],
-            &vec![4, 5, 6, 7],
-            &vec![8, 9, 10, 11],
-            12,
-            a,
-            n,
-        );
+        let u = me_const(&[0, 1, 2, 3], &[4, 5, 6, 7], &[8, 9, 10, 11], 12, a, n);
         for x in 1..8 {
             let q_in = Qubits::from_num(13, x);
             let q_out3 = u.apply(q_in);
@@ -359,11 +348,11 @@ fn test_me_const() {
 #[test]
 fn test_qft() {
     use super::circuits::qft;
-    use super::gates::{H, X};
-    let u = qft(&vec![0, 1, 2, 3]);
-    let mut q_in = Qubits::from_num(4, 0);
+
+    let u = qft(&[0, 1, 2, 3]);
+    let q_in = Qubits::from_num(4, 0);
     let q_out = u.apply(q_in);
-    let bits = vec![Comp::new(0.25, 0.0); (1 << 4)];
+    let bits = vec![Comp::new(0.25, 0.0); 1 << 4];
     let expected = Qubits::from_bits(4, bits);
     // q_out.print_cmps();
     isequal_qubits(&q_out, &expected);
@@ -374,8 +363,8 @@ fn test_iqft() {
     use super::circuits::{inv_qft, qft};
     use super::gates::H;
 
-    let u = qft(&vec![0, 1, 2, 3]);
-    let u2 = inv_qft(&vec![0, 1, 2, 3]);
+    let u = qft(&[0, 1, 2, 3]);
+    let u2 = inv_qft(&[0, 1, 2, 3]);
     let q_in = H::new(0).apply(Qubits::from_num(4, 2));
     let expected = H::new(0).apply(Qubits::from_num(4, 2));
     let q_out = u.apply(q_in);
@@ -392,29 +381,29 @@ fn test_iqft() {
 
 #[test]
 fn test_phase_estimation() {
-    use super::circuits::{inv_qft, swap};
+    use super::circuits::inv_qft;
     use super::gates::H;
-    use super::gates::{CU, R, U};
+    use super::gates::{Pow, CU, R, U};
 
     let x = vec![0, 1, 2, 3];
     fn tar_u() -> U {
         let r = R::new(4, PI * 2.0 * 0.125);
-        return U::new(vec![Box::new(r)], String::from("u"));
+        U::new(vec![Box::new(r)], String::from("u"))
     }
 
     let mut pe_gates: Vec<Box<dyn Operator>> = Vec::new();
-    for i in 0..x.len() {
-        pe_gates.push(Box::new(H::new(x[i])));
+    for x_i in x.iter() {
+        pe_gates.push(Box::new(H::new(*x_i)));
     }
 
-    for i in 0..x.len() {
+    for (i, x_i) in x.iter().copied().enumerate() {
         println!("x[{}]", i);
-        let x_i = x[i];
-        let mut u_i_gates: Vec<Box<dyn Operator>> = Vec::new();
-        for _ in 0..(1 << i) {
-            u_i_gates.push(Box::new(tar_u()));
-        }
-        pe_gates.push(Box::new(CU::new(x_i, u_i_gates, format!("u^2^{i}"))));
+        let u_i = Pow::new(Box::new(tar_u()), 1 << i);
+        pe_gates.push(Box::new(CU::new(
+            x_i,
+            vec![Box::new(u_i)],
+            format!("u^2^{i}"),
+        )));
     }
 
     let iqft = inv_qft(&x);
@@ -442,11 +431,11 @@ fn isequal_qubits(a: &Qubits, b: &Qubits) -> bool {
         );
     }
 
-    return true;
+    true
 }
 
 fn isequal_comp(a: &Comp, b: &Comp) -> bool {
-    return isequal_f64(a.0, b.0) & isequal_f64(a.1, b.1);
+    isequal_f64(a.0, b.0) & isequal_f64(a.1, b.1)
 }
 
 fn isequal_probs(a: Vec<f64>, b: Vec<f64>) -> bool {
@@ -455,9 +444,2014 @@ fn isequal_probs(a: Vec<f64>, b: Vec<f64>) -> bool {
         assert!(isequal_f64(a[i], b[i]), "a[{}] and b[{}] is not same", i, i);
     }
 
-    return true;
+    true
 }
 
 fn isequal_f64(a: f64, b: f64) -> bool {
-    return (a - b).abs() < 1e-9;
+    (a - b).abs() < 1e-9
+}
+
+#[test]
+fn test_classical_matches_qubits() {
+    use super::circuits::mod_add_const;
+    use super::classical::run_classical;
+    let n = 7;
+    for a in 0..n {
+        let u = mod_add_const(&[0, 1, 2], 3, a, n);
+        for b in 0..n {
+            let q_out = u.apply(Qubits::from_num(4, b));
+            let actual = run_classical(&u, 4, b as u128).unwrap();
+            assert_eq!(actual as usize, q_out.pop_most_plausible());
+        }
+    }
+}
+
+#[test]
+fn test_classical_40bits() {
+    use super::circuits::{cmm_const, mod_add_const, wrapping_qadd_const};
+    use super::classical::run_classical;
+    let b: Vec<usize> = (0..40).collect();
+    let n: usize = (1 << 40) - 87;
+    let inputs = [
+        (0, 0),
+        (1, n - 1),
+        (n - 1, n - 1),
+        (987_654_321_987, 123_456_789),
+    ];
+    for (a, x) in inputs {
+        let u = mod_add_const(&b, 40, a, n);
+        let actual = run_classical(&u, 41, x as u128).unwrap();
+        assert_eq!(actual, ((a + x) % n) as u128);
+
+        let u = wrapping_qadd_const(&b, a);
+        let actual = run_classical(&u, 40, x as u128).unwrap();
+        assert_eq!(actual, ((a + x) & ((1 << 40) - 1)) as u128);
+    }
+
+    // |x⟩|0⟩|0⟩|1⟩ → |x⟩|ax mod N⟩|0⟩|1⟩ on 2 * 20 + 2 qubits
+    let x: Vec<usize> = (0..20).collect();
+    let tar: Vec<usize> = (20..40).collect();
+    let n: usize = (1 << 20) - 3;
+    let (a, xv) = (654_321, 999_999);
+    let u = cmm_const(&x, &tar, 40, 41, a, n);
+    let actual = run_classical(&u, 42, xv as u128 | (1 << 41)).unwrap();
+    assert_eq!(actual, (xv | ((a * xv) % n) << 20 | (1 << 41)) as u128);
+}
+
+#[test]
+fn test_classical_rejects_superposition() {
+    use super::classical::run_classical;
+    use super::gates::{H, U, X, Z};
+    let u = U::new(
+        vec![Box::new(X::new(0)), Box::new(Z::new(0))],
+        String::from("xz"),
+    );
+    assert_eq!(run_classical(&u, 1, 0), Ok(1));
+    let err = run_classical(&H::new(0), 1, 0).unwrap_err();
+    assert_eq!(err.gate, "H(0)");
+}
+
+#[test]
+fn test_sparse_matches_qubits() {
+    use super::circuits::{mod_add_const, qft};
+    use super::gates::H;
+    use super::sparse::SparseQubits;
+
+    let u = qft(&[0, 1, 2, 3]);
+    let q_in = H::new(1).apply(Qubits::from_num(4, 5));
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&u).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &u.apply(q_in));
+
+    let u = mod_add_const(&[0, 1, 2], 3, 4, 7);
+    let q_in = H::new(0).apply(Qubits::from_num(4, 2));
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&u).unwrap();
+    assert_eq!(sparse.len(), 2);
+    isequal_qubits(&sparse.to_qubits(), &u.apply(q_in));
+}
+
+#[test]
+fn test_sparse_prunes() {
+    use super::gates::H;
+    use super::sparse::SparseQubits;
+    let mut q = SparseQubits::zeros(3);
+    q.apply(&H::new(2)).unwrap();
+    assert_eq!(q.len(), 2);
+    q.apply(&H::new(2)).unwrap();
+    assert_eq!(q.len(), 1);
+    assert!(isequal_comp(&q.amplitude(0), &Comp::new(1.0, 0.0)));
+}
+
+#[test]
+fn test_stabilizer_matches_qubits() {
+    use super::gates::{OperatorVec, PushOps, CU, CX, H, R, U, X, Y, Z};
+    use super::stabilizer::StabilizerState;
+    use rand::prelude::*;
+
+    let n = 4;
+    let mut rng = StdRng::seed_from_u64(28);
+    for _ in 0..20 {
+        let mut circ = OperatorVec::new();
+        for _ in 0..30 {
+            let a = rng.gen_range(0..n);
+            let b = (a + rng.gen_range(1..n)) % n;
+            match rng.gen_range(0..9) {
+                0 => circ.push_ops(H::new(a)),
+                1 => circ.push_ops(X::new(a)),
+                2 => circ.push_ops(Y::new(a)),
+                3 => circ.push_ops(Z::new(a)),
+                4 => circ.push_ops(R::new(a, PI / 2.0)),
+                5 => circ.push_ops(R::new(a, 3.0 * PI / 2.0)),
+                6 => circ.push_ops(CX::new(a, b)),
+                7 => circ.push_ops(CU::new(a, vec![Box::new(Y::new(b))], String::from("cy"))),
+                _ => circ.push_ops(CU::new(a, vec![Box::new(Z::new(b))], String::from("cz"))),
+            }
+        }
+        let u = U::new(circ, String::from("clifford"));
+        let mut state = StabilizerState::new(n);
+        state.apply(&u).unwrap();
+        let q = u.apply(Qubits::zeros(n));
+
+        // every stabilizer generator must leave the dense state unchanged
+        for stab in state.stabilizers() {
+            let mut q_stab = q.clone();
+            let mut sign = if stab.starts_with('-') { -1.0 } else { 1.0 };
+            for (j, p) in stab.chars().skip(1).enumerate() {
+                q_stab = match p {
+                    'X' => X::new(j).apply(q_stab),
+                    'Y' => {
+                        // Y of this crate is -Y
+                        sign *= -1.0;
+                        Y::new(j).apply(q_stab)
+                    }
+                    'Z' => Z::new(j).apply(q_stab),
+                    _ => q_stab,
+                };
+            }
+            for i in 0..(1 << n) {
+                assert!(
+                    isequal_comp(&(q_stab.bits[i] * sign), &q.bits[i]),
+                    "{}",
+                    stab
+                );
+            }
+        }
+        for j in 0..n {
+            let p1 = q._measure(&[j])[1];
+            match state.deterministic_outcome(j) {
+                Some(true) => assert!(isequal_f64(p1, 1.0)),
+                Some(false) => assert!(isequal_f64(p1, 0.0)),
+                None => assert!(isequal_f64(p1, 0.5)),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_stabilizer_sampling() {
+    use super::gates::{CX, H};
+    use super::stabilizer::StabilizerState;
+    use rand::prelude::*;
+    let mut state = StabilizerState::new(200);
+    state.apply(&H::new(0)).unwrap();
+    for i in 1..200 {
+        state.apply(&CX::new(0, i)).unwrap();
+    }
+    let mut rng = StdRng::seed_from_u64(28);
+    let samples = state.sample_with(&[0, 99, 199], 200, &mut rng);
+    assert!(samples.iter().all(|s| *s == 0 || *s == 0b111));
+    let ones = samples.iter().filter(|s| **s == 0b111).count();
+    assert!(ones > 50 && ones < 150);
+}
+
+#[test]
+fn test_stabilizer_rejects_non_clifford() {
+    use super::gates::{CCX, CX, H, R, U};
+    use super::stabilizer::StabilizerState;
+    let mut state = StabilizerState::new(3);
+    assert!(state.apply(&R::new(0, PI / 2.0)).is_ok());
+    let err = state.apply(&R::new(0, PI / 4.0)).unwrap_err();
+    assert!(err.gate.starts_with("R_"));
+    let err = state.apply(&CCX::new(0, 1, 2)).unwrap_err();
+    assert_eq!(err.gate, "CCX([0,1]->2)");
+
+    // a non-Clifford gate in the middle of a circuit leaves the state untouched
+    let before = state.stabilizers();
+    let u = U::new(
+        vec![
+            Box::new(H::new(1)),
+            Box::new(CX::new(1, 2)),
+            Box::new(R::new(0, PI / 4.0)),
+            Box::new(H::new(2)),
+        ],
+        String::from("half_clifford"),
+    );
+    assert!(state.apply(&u).is_err());
+    assert_eq!(state.stabilizers(), before);
+}
+
+#[test]
+fn test_mps_matches_qubits() {
+    use super::circuits::qft;
+    use super::gates::{CU, CX, H, R, U, Y};
+    use super::mps::MatrixProductState;
+
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(H::new(0)),
+        Box::new(H::new(3)),
+        Box::new(CX::new(0, 4)),
+        Box::new(CX::new(4, 1)),
+        Box::new(R::new(2, 0.3)),
+        Box::new(CU::new(3, vec![Box::new(Y::new(0))], String::from("cy"))),
+        Box::new(qft(&[0, 1, 2, 3, 4])),
+    ];
+    let u = U::new(gates, String::from("circ"));
+    let mut mps = MatrixProductState::new(5, 32);
+    mps.apply(&u).unwrap();
+    assert!(mps.truncation_error() < 1e-12);
+    let expected = u.apply(Qubits::zeros(5));
+    isequal_qubits(&mps.to_qubits(), &expected);
+    for j in 0..5 {
+        assert!(isequal_f64(mps.prob_one(j), expected._measure(&[j])[1]));
+    }
+}
+
+#[test]
+fn test_mps_truncation() {
+    use super::gates::{CCX, CX, H, R};
+    use super::mps::MatrixProductState;
+
+    let mut mps = MatrixProductState::new(60, 2);
+    mps.apply(&H::new(0)).unwrap();
+    for i in 1..60 {
+        mps.apply(&CX::new(i - 1, i)).unwrap();
+    }
+    assert!(mps.bond_dimensions().iter().all(|d| *d == 2));
+    assert!(mps.truncation_error() < 1e-12);
+    let first = mps.measure(0);
+    let expected = if first { 1.0 } else { 0.0 };
+    assert!(isequal_f64(mps.prob_one(59), expected));
+
+    // entangling more than the bond allows loses weight
+    let mut mps = MatrixProductState::new(6, 2);
+    for i in 0..6 {
+        mps.apply(&H::new(i)).unwrap();
+        mps.apply(&R::new(i, 0.1 * i as f64)).unwrap();
+    }
+    for i in 0..3 {
+        mps.apply(&CX::new(i, 5 - i)).unwrap();
+        mps.apply(&H::new(i)).unwrap();
+        mps.apply(&CX::new(5 - i, i)).unwrap();
+    }
+    assert!(mps.truncation_error() > 1e-3);
+    assert!(mps.apply(&CCX::new(0, 1, 2)).is_ok());
+}
+
+#[test]
+fn test_mps_multi_controlled() {
+    use super::gates::{PermutationOracle, PhaseOracle, CCX, CNX, CSWAP, CU, CX, H, MCZ, RY, U, X};
+    use super::mps::MatrixProductState;
+
+    let mut gates: Vec<Box<dyn Operator>> = Vec::new();
+    for i in 0..6 {
+        gates.push(Box::new(RY::new(i, 0.3 + 0.4 * i as f64)));
+    }
+    gates.push(Box::new(CX::new(0, 5)));
+    // controls far apart, out of order and on both sides of the target
+    gates.push(Box::new(CCX::new(5, 1, 3)));
+    gates.push(Box::new(CNX::new_polarized(
+        vec![4, 0, 2],
+        vec![true, false, true],
+        1,
+    )));
+    gates.push(Box::new(CSWAP::new(3, 0, 5)));
+    gates.push(Box::new(MCZ::new(vec![1, 2, 4, 5])));
+    gates.push(Box::new(CU::new_multi(
+        vec![2, 5],
+        vec![Box::new(H::new(0)), Box::new(X::new(3))],
+        String::from("cc-hx"),
+    )));
+    gates.push(Box::new(PhaseOracle::new(
+        vec![4, 1, 3],
+        |x| 0.5 * x as f64,
+        String::from("ph"),
+    )));
+    gates.push(Box::new(PermutationOracle::bijective(
+        vec![0, 2, 5],
+        |x| (5 * x + 3) % 8,
+        String::from("affine"),
+    )));
+    let u = U::new(gates, String::from("multi"));
+
+    let mut mps = MatrixProductState::new(6, 64);
+    mps.apply(&u).unwrap();
+    assert!(mps.truncation_error() < 1e-12);
+    isequal_qubits(&mps.to_qubits(), &u.apply(Qubits::zeros(6)));
+}
+
+#[test]
+fn test_backends_run_same_circuit() {
+    use super::circuits::wrapping_qadd_const;
+    use super::classical::BasisState;
+    use super::density::DensityMatrix;
+    use super::gates::{CU, CX, H, U, X, Z};
+    use super::mps::MatrixProductState;
+    use super::sparse::SparseQubits;
+    use super::stabilizer::StabilizerState;
+
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(X::new(0)),
+        Box::new(H::new(1)),
+        Box::new(CX::new(1, 2)),
+        Box::new(CU::new(0, vec![Box::new(Z::new(2))], String::from("cz"))),
+    ];
+    let u = U::new(gates, String::from("clifford"));
+    let mut states: Vec<Box<dyn QuantumState>> = vec![
+        Box::new(Qubits::zeros(3)),
+        Box::new(SparseQubits::zeros(3)),
+        Box::new(StabilizerState::new(3)),
+        Box::new(MatrixProductState::new(3, 4)),
+        Box::new(DensityMatrix::zeros(3)),
+    ];
+    for state in states.iter_mut() {
+        state.apply(&u).unwrap();
+        assert!(isequal_probs(
+            state.marginal_probs(&[2, 1, 0]),
+            vec![0.0, 0.0, 0.0, 0.0, 0.5, 0.0, 0.0, 0.5]
+        ));
+        let b1 = state.measure(1);
+        assert_eq!(state.measure(2), b1);
+        assert!(state.measure(0));
+    }
+
+    let add = wrapping_qadd_const(&[0, 1, 2], 5);
+    let mut states: Vec<Box<dyn QuantumState>> = vec![
+        Box::new(Qubits::from_num(3, 6)),
+        Box::new(SparseQubits::from_num(3, 6)),
+        Box::new(BasisState::new(3, 6)),
+        Box::new(DensityMatrix::from_num(3, 6)),
+    ];
+    for state in states.iter_mut() {
+        state.apply(&add).unwrap();
+        assert!(isequal_probs(
+            state.marginal_probs(&[0, 1, 2]),
+            vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]
+        ));
+    }
+}
+
+#[test]
+fn test_density_matrix() {
+    use super::circuits::qft;
+    use super::density::DensityMatrix;
+    use super::gates::{MatrixGate, CCX, CX, H, RY, U};
+    use rand::prelude::*;
+    let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+
+    // pure evolution matches |ψ⟩⟨ψ|, including a 4x4 matrix gate that does not lower
+    let fsim = MatrixGate::new(
+        vec![0, 2],
+        vec![
+            vec![l, o, o, o],
+            vec![o, Comp::new(0.6, 0.0), Comp::new(0.0, -0.8), o],
+            vec![o, Comp::new(0.0, -0.8), Comp::new(0.6, 0.0), o],
+            vec![o, o, o, Comp::new(0.0, 1.0)],
+        ],
+        String::from("fsim"),
+    );
+    let u = U::new(
+        vec![
+            Box::new(H::new(0)),
+            Box::new(RY::new(1, 0.7)),
+            Box::new(CCX::new(0, 1, 2)),
+            Box::new(fsim),
+            Box::new(qft(&[0, 1, 2])),
+        ],
+        String::from("circ"),
+    );
+    let psi = u.apply(Qubits::zeros(3));
+    let mut rho = DensityMatrix::zeros(3);
+    rho.apply(&u).unwrap();
+    let expected = DensityMatrix::from_qubits(&psi);
+    for (x, y) in rho.rho.iter().zip(expected.rho.iter()) {
+        assert!(isequal_comp(x, y));
+    }
+    assert!(isequal_f64(rho.purity(), 1.0));
+    assert!(isequal_probs(
+        rho.marginal_probs(&[0, 2]),
+        psi.marginal_probs(&[0, 2])
+    ));
+
+    // bit-flip channel with p = 0.25 on |0⟩
+    let mut rho = DensityMatrix::zeros(2);
+    let (a, b) = (0.75_f64.sqrt(), 0.5);
+    rho.apply_channel(1, &[[[l * a, o], [o, l * a]], [[o, l * b], [l * b, o]]]);
+    assert!(isequal_probs(rho.marginal_probs(&[1]), vec![0.75, 0.25]));
+    assert!(isequal_f64(rho.purity(), 0.75 * 0.75 + 0.25 * 0.25));
+
+    // full depolarizing noise on one half of a Bell pair leaves it maximally mixed
+    let mut rho = DensityMatrix::zeros(2);
+    rho.apply(&H::new(0)).unwrap();
+    rho.apply(&CX::new(0, 1)).unwrap();
+    rho.depolarize(1, 0.75);
+    assert!(isequal_f64(rho.trace(), 1.0));
+    assert!(isequal_f64(rho.purity(), 0.25));
+    assert!(isequal_probs(
+        rho.marginal_probs(&[0, 1]),
+        vec![0.25, 0.25, 0.25, 0.25]
+    ));
+
+    // measuring one half of a Bell pair collapses the other
+    let mut rng = StdRng::seed_from_u64(30);
+    for _ in 0..10 {
+        let mut rho = DensityMatrix::zeros(2);
+        rho.apply(&H::new(0)).unwrap();
+        rho.apply(&CX::new(0, 1)).unwrap();
+        let b0 = rho.measure_with(0, &mut rng);
+        assert_eq!(rho.measure_with(1, &mut rng), b0);
+        assert!(isequal_f64(rho.trace(), 1.0));
+        assert!(isequal_f64(rho.purity(), 1.0));
+    }
+}
+
+#[test]
+#[should_panic(expected = "not trace preserving")]
+fn test_density_matrix_rejects_bad_channel() {
+    use super::density::DensityMatrix;
+    let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+    DensityMatrix::zeros(1).apply_channel(0, &[[[l, o], [o, l]], [[o, l], [l, o]]]);
+}
+
+#[test]
+fn test_apply_is_atomic() {
+    use super::classical::BasisState;
+    use super::gates::{MatrixGate, H, U, X};
+    use super::mps::MatrixProductState;
+    use super::sparse::SparseQubits;
+    let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+    let swap = MatrixGate::new(
+        vec![0, 1],
+        vec![
+            vec![l, o, o, o],
+            vec![o, o, l, o],
+            vec![o, l, o, o],
+            vec![o, o, o, l],
+        ],
+        String::from("swap"),
+    );
+    // the leading gates lower fine, the matrix gate does not
+    let u = U::new(
+        vec![Box::new(X::new(2)), Box::new(H::new(1)), Box::new(swap)],
+        String::from("partial"),
+    );
+
+    let mut sparse = SparseQubits::from_num(3, 1);
+    assert!(sparse.apply(&u).is_err());
+    assert!(isequal_probs(
+        sparse.marginal_probs(&[0, 1, 2]),
+        vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+    ));
+
+    let mut mps = MatrixProductState::new(3, 4);
+    mps.apply(&X::new(0)).unwrap();
+    assert!(mps.apply(&u).is_err());
+    assert!(isequal_probs(
+        mps.marginal_probs(&[0, 1, 2]),
+        vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]
+    ));
+
+    let mut basis = BasisState::new(3, 1);
+    assert!(basis.apply(&u).is_err());
+    assert_eq!(basis, BasisState::new(3, 1));
+}
+
+#[test]
+fn test_clifford_t_gates() {
+    use super::gates::{PhaseGate, H, R, SX, U, X, Z};
+    let q_in = H::new(0).apply(Qubits::from_num(2, 2));
+
+    let ss = U::new(
+        vec![Box::new(PhaseGate::s(0)), Box::new(PhaseGate::s(0))],
+        String::from("ss"),
+    );
+    isequal_qubits(&ss.apply(q_in.clone()), &Z::new(0).apply(q_in.clone()));
+    isequal_qubits(
+        &PhaseGate::t(0).apply(q_in.clone()),
+        &R::new(0, PI / 4.0).apply(q_in.clone()),
+    );
+    let sxsx = U::new(
+        vec![Box::new(SX::new(0)), Box::new(SX::new(0))],
+        String::from("sxsx"),
+    );
+    isequal_qubits(&sxsx.apply(q_in.clone()), &X::new(0).apply(q_in.clone()));
+
+    let mut s = PhaseGate::s(0);
+    s.inverse();
+    assert_eq!(s.name(), "Sdg(0)");
+    isequal_qubits(
+        &s.apply(q_in.clone()),
+        &PhaseGate::sdg(0).apply(q_in.clone()),
+    );
+    let mut tdg = PhaseGate::tdg(0);
+    tdg.inverse();
+    assert_eq!(tdg.name(), "T(0)");
+    isequal_qubits(
+        &tdg.apply(q_in.clone()),
+        &PhaseGate::t(0).apply(q_in.clone()),
+    );
+    let mut sdg = PhaseGate::sdg(0);
+    sdg.inverse();
+    assert_eq!(sdg.name(), "S(0)");
+    assert_eq!(sdg.describe(), PhaseGate::s(0).describe());
+    isequal_qubits(
+        &sdg.apply(q_in.clone()),
+        &PhaseGate::s(0).apply(q_in.clone()),
+    );
+
+    let mut u = U::new(
+        vec![
+            Box::new(PhaseGate::s(0)),
+            Box::new(PhaseGate::t(0)),
+            Box::new(SX::new(0)),
+        ],
+        String::from("u"),
+    );
+    let q_mid = u.apply(q_in.clone());
+    u.inverse();
+    isequal_qubits(&u.apply(q_mid), &q_in);
+
+    // names survive lowering, e.g. in the errors of the stabilizer backend
+    let mut state = super::stabilizer::StabilizerState::new(1);
+    assert!(state.apply(&PhaseGate::s(0)).is_ok());
+    assert!(state.apply(&SX::new(0)).is_ok());
+    assert_eq!(state.apply(&PhaseGate::t(0)).unwrap_err().gate, "T(0)");
+}
+
+#[test]
+fn test_rotation_gates() {
+    use super::gates::{RX, RY, RZ, U3, X, Y, Z};
+    let q_in = super::gates::H::new(1).apply(Qubits::from_num(2, 1));
+
+    // rotations by π are the Pauli gates up to global phase
+    let global =
+        |q: Qubits, c: Comp| Qubits::from_bits(q.size, q.bits.iter().map(|b| *b * c).collect());
+    isequal_qubits(
+        &RX::new(0, PI).apply(q_in.clone()),
+        &global(X::new(0).apply(q_in.clone()), Comp::new(0.0, -1.0)),
+    );
+    isequal_qubits(
+        &RY::new(0, PI).apply(q_in.clone()),
+        &global(Y::new(0).apply(q_in.clone()), Comp::new(0.0, 1.0)),
+    );
+    isequal_qubits(
+        &RZ::new(0, PI).apply(q_in.clone()),
+        &global(Z::new(0).apply(q_in.clone()), Comp::new(0.0, -1.0)),
+    );
+    // U3(θ, -π/2, π/2) = RX(θ)
+    isequal_qubits(
+        &U3::new(0, 0.7, -PI / 2.0, PI / 2.0).apply(q_in.clone()),
+        &RX::new(0, 0.7).apply(q_in.clone()),
+    );
+
+    let mut u3 = U3::new(0, 0.4, 1.1, -0.3);
+    assert_eq!(u3.name(), "U3_(0.4,1.1,-0.3)(0)");
+    let q_mid = u3.apply(q_in.clone());
+    u3.inverse();
+    isequal_qubits(&u3.apply(q_mid), &q_in);
+    for mut gate in [RX::new(1, 0.3), RX::new(0, -2.0)] {
+        let q_mid = gate.apply(q_in.clone());
+        gate.inverse();
+        isequal_qubits(&gate.apply(q_mid), &q_in);
+    }
+
+    // dagger undoes every rotation, and taking it twice gives the rotation back
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(RX::new(1, 0.3)),
+        Box::new(RY::new(0, 1.3)),
+        Box::new(RY::new(1, -0.6)),
+        Box::new(RZ::new(0, 2.1)),
+        Box::new(RZ::new(1, -0.9)),
+        Box::new(U3::new(1, 0.4, 1.1, -0.3)),
+    ];
+    for gate in gates.iter() {
+        let q_mid = gate.apply(q_in.clone());
+        isequal_qubits(&gate.dagger().apply(q_mid.clone()), &q_in);
+        isequal_qubits(&gate.dagger().dagger().apply(q_in.clone()), &q_mid);
+    }
+
+    // U3(θ, φ, λ)† = U3(-θ, -λ, -φ): φ and λ swap places
+    let u3_dg = U3::new(0, 0.4, 1.1, -0.3).dagger();
+    assert_eq!(u3_dg.name(), "U3_(-0.4,0.3,-1.1)(0)");
+    isequal_qubits(
+        &u3_dg.apply(q_in.clone()),
+        &U3::new(0, -0.4, 0.3, -1.1).apply(q_in.clone()),
+    );
+}
+
+#[test]
+fn test_matrix_gate() {
+    use super::gates::{MatrixGate, CU, CX, H, RY};
+    let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+    let q_in = RY::new(2, 0.8).apply(H::new(0).apply(Qubits::from_num(3, 0b010)));
+
+    // 2x2 matrix reproduces H
+    let s = 1.0 / 2.0_f64.sqrt();
+    let h = MatrixGate::new(
+        vec![1],
+        vec![
+            vec![Comp::new(s, 0.0), Comp::new(s, 0.0)],
+            vec![Comp::new(s, 0.0), Comp::new(-s, 0.0)],
+        ],
+        String::from("h"),
+    );
+    isequal_qubits(&h.apply(q_in.clone()), &H::new(1).apply(q_in.clone()));
+
+    // 4x4 matrix on targets [control, target] reproduces CX
+    let cx = MatrixGate::new(
+        vec![2, 0],
+        vec![
+            vec![l, o, o, o],
+            vec![o, o, o, l],
+            vec![o, o, l, o],
+            vec![o, l, o, o],
+        ],
+        String::from("cx"),
+    );
+    assert_eq!(cx.name(), "M[cx](2,0)");
+    isequal_qubits(&cx.apply(q_in.clone()), &CX::new(2, 0).apply(q_in.clone()));
+
+    // controlled and inverted
+    let fsim = MatrixGate::new(
+        vec![0, 2],
+        vec![
+            vec![l, o, o, o],
+            vec![o, Comp::new(0.6, 0.0), Comp::new(0.0, -0.8), o],
+            vec![o, Comp::new(0.0, -0.8), Comp::new(0.6, 0.0), o],
+            vec![o, o, o, Comp::new(0.0, 1.0)],
+        ],
+        String::from("fsim"),
+    );
+    let mut cu = CU::new(1, vec![Box::new(fsim)], String::from("c-fsim"));
+    let q_mid = cu.apply(q_in.clone());
+    cu.inverse();
+    isequal_qubits(&cu.apply(q_mid), &q_in);
+}
+
+#[test]
+#[should_panic(expected = "not unitary")]
+fn test_matrix_gate_rejects_non_unitary() {
+    use super::gates::MatrixGate;
+    let l = Comp::new(1.0, 0.0);
+    MatrixGate::new(vec![0], vec![vec![l, l], vec![l, l]], String::from("bad"));
+}
+
+#[test]
+fn test_swap_gates() {
+    use super::gates::{MatrixGate, CCX, CSWAP, CU, CX, H, ISWAP, RY, SWAP, U};
+    use super::sparse::SparseQubits;
+    let (o, l, i) = (Comp::zero(), Comp::new(1.0, 0.0), Comp::new(0.0, 1.0));
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(4))));
+
+    let cxs = U::new(
+        vec![
+            Box::new(CX::new(0, 2)),
+            Box::new(CX::new(2, 0)),
+            Box::new(CX::new(0, 2)),
+        ],
+        String::from("cxs"),
+    );
+    isequal_qubits(
+        &SWAP::new(0, 2).apply(q_in.clone()),
+        &cxs.apply(q_in.clone()),
+    );
+
+    let iswap = MatrixGate::new(
+        vec![1, 2],
+        vec![
+            vec![l, o, o, o],
+            vec![o, o, i, o],
+            vec![o, i, o, o],
+            vec![o, o, o, l],
+        ],
+        String::from("iswap"),
+    );
+    let mut g = ISWAP::new(1, 2);
+    let q_mid = g.apply(q_in.clone());
+    isequal_qubits(&q_mid, &iswap.apply(q_in.clone()));
+    g.inverse();
+    assert_eq!(g.name(), "ISWAPdg(1,2)");
+    isequal_qubits(&g.apply(q_mid), &q_in);
+
+    let fredkin = U::new(
+        vec![
+            Box::new(CX::new(2, 1)),
+            Box::new(CCX::new(0, 1, 2)),
+            Box::new(CX::new(2, 1)),
+        ],
+        String::from("fredkin"),
+    );
+    isequal_qubits(
+        &CSWAP::new(0, 1, 2).apply(q_in.clone()),
+        &fredkin.apply(q_in.clone()),
+    );
+
+    // lowering, with an extra control, agrees with the native paths
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(SWAP::new(0, 2)),
+        Box::new(ISWAP::new(2, 1)),
+        Box::new(CSWAP::new(1, 0, 2)),
+    ];
+    let cu = CU::new(3, gates, String::from("c-swaps"));
+    let q_in = H::new(3).apply(q_in);
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&cu).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &cu.apply(q_in));
+}
+
+#[test]
+fn test_controlled_phase_gates() {
+    use super::gates::{CPhase, CU, CZ, H, MCZ, R, RY, Z};
+    use super::sparse::SparseQubits;
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(3))));
+
+    let cu_z = CU::new(0, vec![Box::new(Z::new(2))], String::from("cz"));
+    isequal_qubits(
+        &CZ::new(0, 2).apply(q_in.clone()),
+        &cu_z.apply(q_in.clone()),
+    );
+    isequal_qubits(
+        &CZ::new(2, 0).apply(q_in.clone()),
+        &cu_z.apply(q_in.clone()),
+    );
+
+    let cu_r = CU::new(1, vec![Box::new(R::new(2, 0.7))], String::from("cr"));
+    let mut cp = CPhase::new(1, 2, 0.7);
+    let q_mid = cp.apply(q_in.clone());
+    isequal_qubits(&q_mid, &cu_r.apply(q_in.clone()));
+    cp.inverse();
+    isequal_qubits(&cp.apply(q_mid), &q_in);
+
+    let mcz = MCZ::new(vec![0, 1, 2]);
+    let q_out = mcz.apply(q_in.clone());
+    for i in 0..8 {
+        let sign = if i == 0b111 { -1.0 } else { 1.0 };
+        assert!(isequal_comp(
+            &q_out.bits[i],
+            &(q_in.bits[i] * Comp::new(sign, 0.0))
+        ));
+    }
+
+    // lowered paths agree with the native ones
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(CZ::new(0, 1)),
+        Box::new(CPhase::new(2, 0, 1.1)),
+        Box::new(mcz),
+    ];
+    let u = super::gates::U::new(gates, String::from("phases"));
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&u).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &u.apply(q_in));
+}
+
+#[test]
+fn test_negative_controls() {
+    use super::core::BitSlideIndex;
+    use super::gates::{CCX, CNX, CU, CX, H, RY, U, X};
+    use super::sparse::SparseQubits;
+
+    // bit 1 must be 0 and bit 2 must be 1
+    let indices: Vec<usize> = BitSlideIndex::new_with(16, 0b0110, 0b0100).collect();
+    assert_eq!(indices, vec![0b0100, 0b0101, 0b1100, 0b1101]);
+    let indices: Vec<usize> = BitSlideIndex::new(8, 0b010).merge_with(0b100, 0).collect();
+    assert_eq!(indices, vec![0b010, 0b011]);
+
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(4))));
+    // reference: conjugate the negative controls with X
+    let conjugated = |bits: Vec<usize>, gate: Box<dyn Operator>| {
+        let mut gates: Vec<Box<dyn Operator>> = Vec::new();
+        for b in bits.iter() {
+            gates.push(Box::new(X::new(*b)));
+        }
+        gates.push(gate);
+        for b in bits.iter() {
+            gates.push(Box::new(X::new(*b)));
+        }
+        U::new(gates, String::from("conjugated"))
+    };
+
+    let cx = CX::new_polarized(0, false, 3);
+    assert_eq!(cx.name(), "CX(!0->3)");
+    let expected = conjugated(vec![0], Box::new(CX::new(0, 3))).apply(q_in.clone());
+    isequal_qubits(&cx.apply(q_in.clone()), &expected);
+
+    let ccx = CCX::new_polarized(1, false, 2, true, 0);
+    let expected = conjugated(vec![1], Box::new(CCX::new(1, 2, 0))).apply(q_in.clone());
+    isequal_qubits(&ccx.apply(q_in.clone()), &expected);
+
+    let cnx = CNX::new_polarized(vec![0, 1, 2], vec![false, true, false], 3);
+    let expected = conjugated(vec![0, 2], Box::new(CNX::new(vec![0, 1, 2], 3))).apply(q_in.clone());
+    isequal_qubits(&cnx.apply(q_in.clone()), &expected);
+
+    let cu = CU::new_polarized(
+        2,
+        false,
+        vec![Box::new(H::new(3)), Box::new(cx)],
+        String::from("cu"),
+    );
+    let cu_ref = CU::new(
+        2,
+        vec![
+            Box::new(H::new(3)),
+            Box::new(CX::new_polarized(0, false, 3)),
+        ],
+        String::from("cu"),
+    );
+    let expected = conjugated(vec![2], Box::new(cu_ref)).apply(q_in.clone());
+    isequal_qubits(&cu.apply(q_in.clone()), &expected);
+
+    // lowering agrees with the native paths
+    let u = U::new(
+        vec![Box::new(ccx), Box::new(cnx), Box::new(cu)],
+        String::from("negatives"),
+    );
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&u).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &u.apply(q_in));
+}
+
+#[test]
+fn test_multi_controlled_cu() {
+    use super::gates::{CU, CX, H, RY};
+    use super::sparse::SparseQubits;
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(4))));
+    let body = || -> Vec<Box<dyn Operator>> { vec![Box::new(H::new(3)), Box::new(CX::new(3, 1))] };
+
+    // equivalent to nesting single-controlled CUs
+    let nested = CU::new(
+        0,
+        vec![Box::new(CU::new_polarized(
+            2,
+            false,
+            body(),
+            String::from("inner"),
+        ))],
+        String::from("outer"),
+    );
+    let mut multi =
+        CU::new_multi_polarized(vec![0, 2], vec![true, false], body(), String::from("cc"));
+    assert!(multi.name().starts_with("CU(0,!2->"));
+    let q_mid = multi.apply(q_in.clone());
+    isequal_qubits(&q_mid, &nested.apply(q_in.clone()));
+
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&multi).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &q_mid);
+
+    multi.inverse();
+    isequal_qubits(&multi.apply(q_mid), &q_in);
+}
+
+#[test]
+fn test_dagger() {
+    use super::circuits::qft;
+    use super::gates::*;
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(4))));
+    let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+    let gates: Vec<Box<dyn Operator>> = vec![
+        Box::new(H::new(0)),
+        Box::new(X::new(1)),
+        Box::new(Y::new(2)),
+        Box::new(Z::new(3)),
+        Box::new(R::new(0, 0.4)),
+        Box::new(PhaseGate::s(1)),
+        Box::new(PhaseGate::sdg(2)),
+        Box::new(PhaseGate::t(3)),
+        Box::new(PhaseGate::tdg(0)),
+        Box::new(SX::new(1)),
+        Box::new(RX::new(2, 0.3)),
+        Box::new(RY::new(3, 0.5)),
+        Box::new(RZ::new(0, 0.7)),
+        Box::new(U3::new(1, 0.2, 0.9, -0.4)),
+        Box::new(CX::new(0, 1)),
+        Box::new(CZ::new(1, 2)),
+        Box::new(CPhase::new(2, 3, 0.6)),
+        Box::new(CCX::new(0, 1, 2)),
+        Box::new(CNX::new(vec![0, 1, 2], 3)),
+        Box::new(MCZ::new(vec![0, 2, 3])),
+        Box::new(SWAP::new(0, 3)),
+        Box::new(ISWAP::new(1, 3)),
+        Box::new(CSWAP::new(0, 1, 2)),
+        Box::new(MatrixGate::new(
+            vec![2],
+            vec![vec![o, Comp::new(0.0, 1.0)], vec![l, o]],
+            String::from("m"),
+        )),
+        Box::new(CU::new(
+            3,
+            vec![Box::new(PhaseGate::s(0)), Box::new(RX::new(1, 0.2))],
+            String::from("cu"),
+        )),
+        Box::new(qft(&[0, 1, 2, 3])),
+    ];
+    for gate in gates.iter() {
+        let name = gate.name();
+        let q_mid = gate.apply(q_in.clone());
+        let inv = gate.dagger();
+        isequal_qubits(&inv.apply(q_mid), &q_in);
+        // the original is untouched
+        assert_eq!(gate.name(), name);
+    }
+
+    // the whole circuit at once
+    let u = U::new(gates, String::from("all"));
+    let q_mid = u.apply(q_in.clone());
+    isequal_qubits(&u.adjoint().apply(q_mid), &q_in);
+}
+
+#[test]
+fn test_clone_operators() {
+    use super::circuits::qft;
+    use super::gates::{CU, H, RY, U};
+    let q_in = RY::new(2, 0.8).apply(RY::new(1, 0.3).apply(H::new(0).apply(Qubits::zeros(4))));
+
+    let u = qft(&[0, 1, 2]);
+    let mut u_inv = u.clone();
+    u_inv.inverse();
+    isequal_qubits(&u_inv.apply(u.apply(q_in.clone())), &q_in);
+
+    let boxed: Box<dyn Operator> = Box::new(CU::from_u(3, u.clone()));
+    let copy = boxed.clone();
+    assert_eq!(copy.name(), boxed.name());
+    isequal_qubits(&copy.apply(q_in.clone()), &boxed.apply(q_in.clone()));
+
+    let gates: Vec<Box<dyn Operator>> = vec![boxed, Box::new(RY::new(0, 0.1))];
+    let twice = U::new(
+        [gates.clone(), gates.clone()].concat(),
+        String::from("twice"),
+    );
+    let once = U::new(gates, String::from("once"));
+    isequal_qubits(&twice.apply(q_in.clone()), &once.apply(once.apply(q_in)));
+}
+
+#[test]
+fn test_describe() {
+    use super::circuits::qft;
+    use super::core::{BitSlideIndex, GateInfo, GateKind};
+    use super::gates::{PhaseGate, CNX, CU, MCZ, U3};
+
+    let mut s = PhaseGate::s(2);
+    assert_eq!(
+        s.describe(),
+        GateInfo::new(GateKind::S, vec![2], vec![], vec![])
+    );
+    s.inverse();
+    assert_eq!(s.describe().kind, GateKind::Sdg);
+
+    let u3 = U3::new(0, 0.1, 0.2, 0.3).describe();
+    assert_eq!(u3.params, vec![0.1, 0.2, 0.3]);
+
+    let cnx = CNX::new_polarized(vec![0, 1], vec![true, false], 4).describe();
+    assert_eq!(cnx.kind, GateKind::CNX);
+    assert_eq!(cnx.targets, vec![4]);
+    assert_eq!(cnx.controls, vec![0, 1]);
+    assert_eq!(cnx.polarities, vec![true, false]);
+
+    // walk a circuit: qft on n qubits has n H and n(n-1)/2 CPhase
+    fn count(info: &GateInfo, kind: GateKind) -> usize {
+        let own = if info.kind == kind { 1 } else { 0 };
+        own + info.children.iter().map(|c| count(c, kind)).sum::<usize>()
+    }
+    let cu = CU::from_u(5, qft(&[0, 1, 2, 3, 4]));
+    let info = cu.describe();
+    assert_eq!(info.label, "qft");
+    assert_eq!(count(&info, GateKind::H), 5);
+    assert_eq!(count(&info, GateKind::CPhase), 10);
+    assert_eq!(count(&info, GateKind::SWAP), 2);
+    assert_eq!(info.qubits(), vec![0, 1, 2, 3, 4, 5]);
+
+    // MCZ reports its last bit as the target, like CZ
+    let mcz = MCZ::new(vec![3, 0, 2]).describe();
+    assert_eq!(mcz.targets, vec![2]);
+    assert_eq!(mcz.controls, vec![3, 0]);
+
+    // a gate that does not override describe is opaque
+    struct Identity;
+    impl Applicable for Identity {
+        fn name(&self) -> String {
+            String::from("Identity")
+        }
+        fn apply_iter(&self, qubits: Qubits, _iter: &BitSlideIndex) -> Qubits {
+            qubits
+        }
+    }
+    let info = Identity.describe();
+    assert_eq!(info.kind, GateKind::Opaque);
+    assert_eq!(info.label, "Identity");
+    assert!(info.qubits().is_empty());
+}
+
+#[test]
+fn test_parameterized_circuit() {
+    use super::gates::{CPhase, PushOps, CX, H, RY, RZ, U, U3};
+    use super::params::{Angle, ParamGate, ParameterizedCircuit};
+    use std::collections::HashMap;
+
+    let mut circ = ParameterizedCircuit::new(String::from("ansatz"));
+    circ.push_ops(H::new(0));
+    circ.push_param(ParamGate::RY(1, Angle::symbol("a")));
+    circ.push_ops(CX::new(0, 1));
+    circ.push_param(ParamGate::CPhase(1, 2, Angle::scaled("b", -2.0)));
+    circ.push_param(ParamGate::U3(
+        2,
+        Angle::symbol("a"),
+        Angle::value(0.3),
+        Angle::symbol("b"),
+    ));
+    circ.push_param(ParamGate::RZ(0, Angle::value(0.7)));
+    assert_eq!(
+        circ.parameters(),
+        vec![String::from("a"), String::from("b")]
+    );
+
+    let mut values = HashMap::new();
+    values.insert(String::from("a"), 0.4);
+    values.insert(String::from("b"), -1.1);
+    let bound = circ.bind(&values);
+    let expected = U::new(
+        vec![
+            Box::new(H::new(0)),
+            Box::new(RY::new(1, 0.4)),
+            Box::new(CX::new(0, 1)),
+            Box::new(CPhase::new(1, 2, 2.2)),
+            Box::new(U3::new(2, 0.4, 0.3, -1.1)),
+            Box::new(RZ::new(0, 0.7)),
+        ],
+        String::from("expected"),
+    );
+    let q_in = Qubits::zeros(3);
+    isequal_qubits(&bound.apply(q_in.clone()), &expected.apply(q_in));
+}
+
+#[test]
+#[should_panic(expected = "unbound parameter")]
+fn test_parameterized_circuit_unbound() {
+    use super::params::{Angle, ParamGate, ParameterizedCircuit};
+    let mut circ = ParameterizedCircuit::new(String::from("ansatz"));
+    circ.push_param(ParamGate::RX(0, Angle::symbol("a")));
+    circ.bind(&std::collections::HashMap::new());
+}
+
+#[test]
+fn test_observable() {
+    use super::gates::{RX, Y};
+    use super::observable::{Observable, Pauli, PauliString};
+    // RX(a)|0⟩ = cos(a/2)|0⟩ - i sin(a/2)|1⟩: ⟨Z⟩ = cos(a), ⟨Y⟩ = -sin(a), ⟨X⟩ = 0
+    let a = 0.7;
+    let q = RX::new(0, a).apply(Qubits::zeros(1));
+    let pauli = |p| PauliString::new(vec![(0, p)]);
+    assert!(isequal_f64(pauli(Pauli::Z).expectation(&q), a.cos()));
+    assert!(isequal_f64(pauli(Pauli::Y).expectation(&q), -a.sin()));
+    assert!(isequal_f64(pauli(Pauli::X).expectation(&q), 0.0));
+    assert!(isequal_f64(pauli(Pauli::I).expectation(&q), 1.0));
+    // textbook Y is minus the Y gate
+    let q_in = RX::new(0, 0.3).apply(Qubits::zeros(1));
+    let y = pauli(Pauli::Y).apply(&q_in);
+    let y_gate = Y::new(0).apply(q_in);
+    for i in 0..2 {
+        assert!(isequal_comp(&y.bits[i], &(y_gate.bits[i] * -1.0)));
+    }
+
+    let mut obs = Observable::new();
+    obs.add_term(2.0, pauli(Pauli::Z));
+    obs.add_term(-0.5, pauli(Pauli::Y));
+    assert!(isequal_f64(
+        obs.expectation(&q),
+        2.0 * a.cos() + 0.5 * a.sin()
+    ));
+}
+
+#[test]
+fn test_gradients() {
+    use super::gates::{PushOps, CX, H};
+    use super::gradient::{adjoint_gradient, expectation, parameter_shift_gradient};
+    use super::observable::{Observable, Pauli, PauliString};
+    use super::params::{Angle, ParamGate, ParameterizedCircuit};
+    use std::collections::HashMap;
+
+    let mut circ = ParameterizedCircuit::new(String::from("ansatz"));
+    circ.push_ops(H::new(0));
+    circ.push_param(ParamGate::RY(1, Angle::symbol("a")));
+    circ.push_param(ParamGate::RX(2, Angle::scaled("b", 1.5)));
+    circ.push_ops(CX::new(0, 1));
+    circ.push_param(ParamGate::CPhase(1, 2, Angle::symbol("c")));
+    circ.push_param(ParamGate::R(0, Angle::scaled("a", -0.5)));
+    circ.push_ops(H::new(2));
+    circ.push_param(ParamGate::U3(
+        1,
+        Angle::symbol("b"),
+        Angle::symbol("c"),
+        Angle::symbol("d"),
+    ));
+    circ.push_param(ParamGate::RZ(0, Angle::symbol("d")));
+    circ.push_ops(CX::new(1, 2));
+    circ.push_param(ParamGate::RY(0, Angle::value(0.4)));
+    circ.push_ops(CX::new(2, 0));
+
+    let mut obs = Observable::new();
+    obs.add_term(1.0, PauliString::new(vec![(0, Pauli::Z), (1, Pauli::X)]));
+    obs.add_term(-0.7, PauliString::new(vec![(2, Pauli::Y)]));
+    obs.add_term(
+        0.3,
+        PauliString::new(vec![(0, Pauli::X), (1, Pauli::Y), (2, Pauli::Z)]),
+    );
+
+    let mut values = HashMap::new();
+    for (name, v) in [("a", 0.3), ("b", -1.2), ("c", 0.8), ("d", 2.1)] {
+        values.insert(String::from(name), v);
+    }
+    let input = Qubits::zeros(3);
+    let shift = parameter_shift_gradient(&circ, &obs, &input, &values);
+    let adjoint = adjoint_gradient(&circ, &obs, &input, &values);
+    assert_eq!(shift.len(), 4);
+
+    let eps = 1e-5;
+    for name in circ.parameters() {
+        let mut plus = values.clone();
+        *plus.get_mut(&name).unwrap() += eps;
+        let mut minus = values.clone();
+        *minus.get_mut(&name).unwrap() -= eps;
+        let fd = (expectation(&circ, &obs, &input, &plus)
+            - expectation(&circ, &obs, &input, &minus))
+            / (2.0 * eps);
+        assert!(
+            (shift[&name] - fd).abs() < 1e-6,
+            "{}: {} vs {}",
+            name,
+            shift[&name],
+            fd
+        );
+        assert!(
+            (adjoint[&name] - fd).abs() < 1e-6,
+            "{}: {} vs {}",
+            name,
+            adjoint[&name],
+            fd
+        );
+    }
+}
+
+#[test]
+#[should_panic(expected = "x0 has no parameters")]
+fn test_nelder_mead_rejects_empty_x0() {
+    use super::optim::{Criteria, NelderMead};
+    NelderMead::new(0.5).minimize(&mut |_| 0.0, &[], &Criteria::new(10, 1e-9), &mut |_| true);
+}
+
+#[test]
+#[should_panic(expected = "x0 has no parameters")]
+fn test_spsa_rejects_empty_x0() {
+    use super::optim::{Criteria, SPSA};
+    SPSA::new(0.2, 0.1).minimize(&mut |_| 0.0, &[], &Criteria::new(10, 1e-9), &mut |_| true);
+}
+
+#[test]
+#[should_panic(expected = "x0 has no parameters")]
+fn test_adam_rejects_empty_x0() {
+    use super::optim::{Adam, Criteria};
+    Adam::new(0.1).minimize(
+        &mut |_| 0.0,
+        &mut |_| vec![],
+        &[],
+        &Criteria::new(10, 1e-9),
+        &mut |_| true,
+    );
+}
+
+#[test]
+fn test_optimizer_loop() {
+    use super::optim::{Adam, Criteria, Iteration, NelderMead, OptimResult, SPSA};
+    use std::cell::Cell;
+    let evals = Cell::new(0);
+    let mut f = |x: &[f64]| {
+        evals.set(evals.get() + 1);
+        (x[0] - 1.0).powi(2) + 3.0 * (x[1] + 2.0).powi(2)
+    };
+    let mut grad = |x: &[f64]| vec![2.0 * (x[0] - 1.0), 6.0 * (x[1] + 2.0)];
+    let x0 = [0.0, 0.0];
+    let mut spsa = SPSA::new(0.2, 0.1);
+    spsa.seed = Some(7);
+
+    // each optimizer with a callback that counts its calls and stops after `stop` of them
+    let mut run = |which: usize, criteria: Criteria, stop: usize| -> (OptimResult, usize) {
+        let mut calls = 0;
+        let mut callback = |_: &Iteration| {
+            calls += 1;
+            calls < stop
+        };
+        evals.set(0);
+        let result = match which {
+            0 => NelderMead::new(0.5).minimize(&mut f, &x0, &criteria, &mut callback),
+            1 => spsa.minimize(&mut f, &x0, &criteria, &mut callback),
+            _ => Adam::new(0.05).minimize(&mut f, &mut grad, &x0, &criteria, &mut callback),
+        };
+        (result, calls)
+    };
+
+    for which in 0..3 {
+        // the callback sees every iteration, up to convergence
+        let (result, calls) = run(which, Criteria::new(5000, 1e-6), usize::MAX);
+        assert!(result.converged);
+        assert_eq!(calls, result.iterations);
+        if which == 1 {
+            assert_eq!(evals.get(), 1 + 3 * result.iterations);
+        }
+        if which == 2 {
+            assert_eq!(evals.get(), 1 + result.iterations);
+        }
+
+        // the callback stops the optimization on the iteration it returns false
+        let (result, calls) = run(which, Criteria::new(5000, 1e-6), 3);
+        assert_eq!((calls, result.iterations), (3, 3));
+        assert!(!result.converged);
+    }
+}
+
+#[test]
+fn test_optimizers() {
+    use super::optim::{Adam, Criteria, NelderMead, SPSA};
+    // quadratic bowl with minimum 0.5 at (1, -2)
+    let mut f = |x: &[f64]| (x[0] - 1.0).powi(2) + 3.0 * (x[1] + 2.0).powi(2) + 0.5;
+    let mut grad = |x: &[f64]| vec![2.0 * (x[0] - 1.0), 6.0 * (x[1] + 2.0)];
+    let x0 = [0.0, 0.0];
+
+    let mut seen = 0;
+    let result =
+        NelderMead::new(0.5).minimize(&mut f, &x0, &Criteria::new(500, 1e-12), &mut |info| {
+            seen = info.iteration;
+            true
+        });
+    assert!(result.converged);
+    assert_eq!(seen, result.iterations);
+    assert!((result.params[0] - 1.0).abs() < 1e-4 && (result.params[1] + 2.0).abs() < 1e-4);
+
+    // Rosenbrock function, minimum 0 at (1, 1) in a curved valley
+    let mut rosenbrock = |x: &[f64]| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+    let result = NelderMead::new(0.5).minimize(
+        &mut rosenbrock,
+        &[-1.2, 1.0],
+        &Criteria::new(5000, 1e-14),
+        &mut |_| true,
+    );
+    assert!(result.converged);
+    assert!((result.params[0] - 1.0).abs() < 1e-3 && (result.params[1] - 1.0).abs() < 1e-3);
+
+    // NaN outside x[0] >= 0 is treated as +inf and steered away from
+    let mut guarded = |x: &[f64]| {
+        if x[0] < 0.0 {
+            f64::NAN
+        } else {
+            (x[0] - 1.0).powi(2) + (x[1] + 2.0).powi(2)
+        }
+    };
+    let result = NelderMead::new(0.5).minimize(
+        &mut guarded,
+        &[0.2, 0.0],
+        &Criteria::new(500, 1e-12),
+        &mut |_| true,
+    );
+    assert!(result.converged);
+    assert!((result.params[0] - 1.0).abs() < 1e-4 && (result.params[1] + 2.0).abs() < 1e-4);
+
+    let mut spsa = SPSA::new(0.2, 0.1);
+    spsa.seed = Some(7);
+    let result = spsa.minimize(&mut f, &x0, &Criteria::new(2000, 1e-9), &mut |_| true);
+    assert!((result.value - 0.5).abs() < 1e-3, "{}", result.value);
+
+    let result = Adam::new(0.05).minimize(
+        &mut f,
+        &mut grad,
+        &x0,
+        &Criteria::new(5000, 1e-6),
+        &mut |_| true,
+    );
+    assert!(result.converged);
+    assert!((result.params[0] - 1.0).abs() < 1e-4 && (result.params[1] + 2.0).abs() < 1e-4);
+
+    // the callback stops the optimization
+    let result = Adam::new(0.05).minimize(
+        &mut f,
+        &mut grad,
+        &x0,
+        &Criteria::new(5000, 1e-6),
+        &mut |info| info.iteration < 3,
+    );
+    assert_eq!(result.iterations, 3);
+    assert!(!result.converged);
+}
+
+#[test]
+fn test_vqe_objective() {
+    use super::gates::{PushOps, CX};
+    use super::observable::{Observable, Pauli, PauliString};
+    use super::optim::{Adam, Criteria, Objective};
+    use super::params::{Angle, ParamGate, ParameterizedCircuit};
+
+    // H = Z0 Z1 + 0.5 (X0 + X1), whose ground energy is -√2
+    let mut obs = Observable::new();
+    obs.add_term(1.0, PauliString::new(vec![(0, Pauli::Z), (1, Pauli::Z)]));
+    obs.add_term(0.5, PauliString::new(vec![(0, Pauli::X)]));
+    obs.add_term(0.5, PauliString::new(vec![(1, Pauli::X)]));
+
+    let mut circ = ParameterizedCircuit::new(String::from("ansatz"));
+    circ.push_param(ParamGate::RY(0, Angle::symbol("a")));
+    circ.push_param(ParamGate::RY(1, Angle::symbol("b")));
+    circ.push_ops(CX::new(0, 1));
+    circ.push_param(ParamGate::RY(0, Angle::symbol("c")));
+    circ.push_param(ParamGate::RY(1, Angle::symbol("d")));
+
+    let objective = Objective::new(&circ, &obs, Qubits::zeros(2));
+    let result = Adam::new(0.05).minimize(
+        &mut |x| objective.cost(x),
+        &mut |x| objective.gradient(x),
+        &[0.1, 0.2, 0.3, 0.4],
+        &Criteria::new(3000, 1e-7),
+        &mut |_| true,
+    );
+    assert!(
+        (result.value + 2.0_f64.sqrt()).abs() < 1e-5,
+        "{}",
+        result.value
+    );
+}
+
+#[test]
+fn test_pauli_rotation() {
+    use super::gates::{PauliRotation, CU, H, RX, RY};
+    use super::pauli::{Pauli, PauliString};
+    use super::sparse::SparseQubits;
+
+    let mut q_in = Qubits::zeros(5);
+    for i in 0..5 {
+        q_in = RY::new(i, 0.3 + 0.4 * i as f64).apply(q_in);
+        q_in = RX::new(i, 0.2 * i as f64).apply(q_in);
+    }
+    let strings = vec![
+        vec![(0, Pauli::Z), (3, Pauli::Z)],
+        vec![(1, Pauli::X)],
+        vec![(0, Pauli::Z), (2, Pauli::Y), (4, Pauli::X)],
+        vec![(4, Pauli::Y), (1, Pauli::I), (0, Pauli::Y)],
+    ];
+    let theta = 0.9;
+    for s in strings {
+        let paulis = PauliString::new(s);
+        let mut rot = PauliRotation::new(paulis.clone(), theta);
+
+        // cos(θ/2)|ψ⟩ - i sin(θ/2)P|ψ⟩
+        let p_psi = paulis.apply(&q_in);
+        let mut expected = q_in.clone();
+        for (e, p) in expected.bits.iter_mut().zip(p_psi.bits.iter()) {
+            *e = *e * (0.5 * theta).cos() + *p * Comp::new(0.0, -(0.5 * theta).sin());
+        }
+        let q_out = rot.apply(q_in.clone());
+        isequal_qubits(&q_out, &expected);
+        isequal_qubits(&rot.decompose().apply(q_in.clone()), &expected);
+
+        // controlled, natively and lowered
+        let free = (0..5)
+            .find(|q| !paulis.paulis.iter().any(|(p, _)| p == q))
+            .unwrap();
+        let cu = CU::new(free, vec![Box::new(rot.clone())], String::from("c-rot"));
+        let cu_ref = CU::new(free, vec![Box::new(rot.decompose())], String::from("c-dec"));
+        let q_h = H::new(free).apply(q_in.clone());
+        let q_c = cu.apply(q_h.clone());
+        isequal_qubits(&q_c, &cu_ref.apply(q_h.clone()));
+        let mut sparse = SparseQubits::from_qubits(&q_h);
+        sparse.apply(&cu).unwrap();
+        isequal_qubits(&sparse.to_qubits(), &q_c);
+
+        rot.inverse();
+        isequal_qubits(&rot.apply(q_out), &q_in);
+    }
+
+    // identity factors are not targets
+    let paulis = PauliString::new(vec![(4, Pauli::Y), (1, Pauli::I), (0, Pauli::Y)]);
+    let info = PauliRotation::new(paulis, theta).describe();
+    assert_eq!(info.targets, vec![4, 0]);
+    assert_eq!(info.qubits(), vec![0, 4]);
+
+    // so a CU may control on an identity position
+    for s in [
+        vec![(0, Pauli::X), (1, Pauli::I), (2, Pauli::Z)],
+        vec![(0, Pauli::Z), (1, Pauli::I), (2, Pauli::Z)],
+    ] {
+        let active: Vec<(usize, Pauli)> =
+            s.iter().filter(|(_, p)| *p != Pauli::I).cloned().collect();
+        let rot = PauliRotation::new(PauliString::new(s), theta);
+        let rot_ref = PauliRotation::new(PauliString::new(active), theta);
+        let q_h = H::new(1).apply(q_in.clone());
+        let cu = CU::new(1, vec![Box::new(rot)], String::from("c-rot"));
+        let cu_ref = CU::new(1, vec![Box::new(rot_ref)], String::from("c-ref"));
+        isequal_qubits(&cu.apply(q_h.clone()), &cu_ref.apply(q_h));
+    }
+}
+
+#[test]
+fn test_phase_oracle() {
+    use super::classical::run_classical;
+    use super::gates::{PhaseOracle, CU, H, RY, U, X};
+    use super::sparse::SparseQubits;
+
+    let mut q_in = Qubits::zeros(4);
+    for i in 0..4 {
+        q_in = RY::new(i, 0.5 + 0.3 * i as f64).apply(q_in);
+    }
+    // register [2, 0, 3]: x = b2 + 2 b0 + 4 b3
+    let mut oracle = PhaseOracle::new(vec![2, 0, 3], |x| 0.1 * (x * x) as f64, String::from("sq"));
+    let q_out = oracle.apply(q_in.clone());
+    for idx in 0..16 {
+        let x = ((idx >> 2) & 1) | ((idx & 1) << 1) | (((idx >> 3) & 1) << 2);
+        let phi = 0.1 * (x * x) as f64;
+        assert!(isequal_comp(
+            &q_out.bits[idx],
+            &(q_in.bits[idx] * Comp::new(phi.cos(), phi.sin()))
+        ));
+    }
+
+    // lowered inside a CU
+    let cu = CU::new(1, vec![Box::new(oracle.clone())], String::from("c-oracle"));
+    let q_c = cu.apply(q_in.clone());
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&cu).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &q_c);
+
+    oracle.inverse();
+    isequal_qubits(&oracle.apply(q_out), &q_in);
+
+    // the oracle can be moved to and shared between threads
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+    assert_send_sync(&oracle);
+    let q_thread = std::thread::spawn(move || oracle.apply(Qubits::from_num(4, 0b1101)))
+        .join()
+        .unwrap();
+    let phi = -0.1 * (7 * 7) as f64;
+    assert!(isequal_comp(
+        &q_thread.bits[0b1101],
+        &Comp::new(phi.cos(), phi.sin())
+    ));
+
+    // a boolean oracle is diagonal, so it passes through the classical simulator
+    let marked = PhaseOracle::from_bool(vec![0, 1, 2], |x| x == 6, String::from("x==6"));
+    assert_eq!(run_classical(&marked, 3, 0b110).unwrap(), 0b110);
+
+    // one Grover iteration on 2 qubits finds the marked item with certainty
+    let diffusion = |gates: &mut Vec<Box<dyn Operator>>| {
+        for i in 0..2 {
+            gates.push(Box::new(H::new(i)));
+            gates.push(Box::new(X::new(i)));
+        }
+        gates.push(Box::new(PhaseOracle::from_bool(
+            vec![0, 1],
+            |x| x == 3,
+            String::from("11"),
+        )));
+        for i in 0..2 {
+            gates.push(Box::new(X::new(i)));
+            gates.push(Box::new(H::new(i)));
+        }
+    };
+    let mut gates: Vec<Box<dyn Operator>> = vec![Box::new(H::new(0)), Box::new(H::new(1))];
+    gates.push(Box::new(PhaseOracle::from_bool(
+        vec![0, 1],
+        |x| x == 2,
+        String::from("2"),
+    )));
+    diffusion(&mut gates);
+    let q_out = U::new(gates, String::from("grover")).apply(Qubits::zeros(2));
+    assert!(isequal_f64(q_out.bits[2].abs_square(), 1.0));
+}
+
+#[test]
+fn test_permutation_oracle() {
+    use super::circuits::wrapping_qadd_const;
+    use super::classical::run_classical;
+    use super::gates::{PermutationOracle, CU, RY};
+    use super::sparse::SparseQubits;
+
+    let mut q_in = Qubits::zeros(5);
+    for i in 0..5 {
+        q_in = RY::new(i, 0.4 + 0.3 * i as f64).apply(q_in);
+    }
+
+    // bijective form against the adder circuit, on a state and on every basis input
+    let b = vec![3, 0, 4];
+    let mut add = PermutationOracle::bijective(b.clone(), |x| (x + 5) % 8, String::from("+5"));
+    let adder = wrapping_qadd_const(&b, 5);
+    let q_out = add.apply(q_in.clone());
+    isequal_qubits(&q_out, &adder.apply(q_in.clone()));
+    for x in 0..32 {
+        assert_eq!(
+            run_classical(&add, 5, x).unwrap(),
+            run_classical(&adder, 5, x).unwrap()
+        );
+    }
+
+    // lowering of a scrambled permutation, with an extra control
+    let table = [6, 3, 0, 7, 1, 5, 2, 4];
+    let perm = PermutationOracle::bijective(vec![0, 2, 4], move |x| table[x], String::from("p"));
+    let cu = CU::new(1, vec![Box::new(perm)], String::from("c-p"));
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&cu).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &cu.apply(q_in.clone()));
+
+    add.inverse();
+    isequal_qubits(&add.apply(q_out), &q_in);
+
+    // xor form is its own inverse
+    let f = PermutationOracle::xor(vec![4, 1], vec![0, 2, 3], |x| 5 * x % 7, String::from("f"));
+    let q_out = f.apply(q_in.clone());
+    isequal_qubits(&f.apply(q_out.clone()), &q_in);
+    let mut sparse = SparseQubits::from_qubits(&q_in);
+    sparse.apply(&f).unwrap();
+    isequal_qubits(&sparse.to_qubits(), &q_out);
+}
+
+#[test]
+#[should_panic(expected = "not a bijection")]
+fn test_permutation_oracle_rejects_non_bijection() {
+    use super::gates::PermutationOracle;
+    PermutationOracle::bijective(vec![0, 1], |x| x / 2, String::from("half"));
+}
+
+#[test]
+fn test_pow() {
+    use super::gates::{
+        CPhase, MatrixGate, PauliRotation, PermutationOracle, PhaseGate, PhaseOracle, Pow, CU, CX,
+        H, ISWAP, R, RY, SWAP, SX, U, U3,
+    };
+    use super::observable::{Pauli, PauliString};
+    use super::sparse::SparseQubits;
+
+    let mut q_in = Qubits::zeros(5);
+    for i in 0..5 {
+        q_in = RY::new(i, 0.4 + 0.5 * i as f64).apply(q_in);
+    }
+    let c = Comp::new(0.6, 0.0);
+    let d = Comp::new(0.0, 0.8);
+    let ops: Vec<Box<dyn Operator>> = vec![
+        Box::new(H::new(1)),
+        Box::new(SWAP::new(0, 2)),
+        Box::new(PhaseGate::s(3)),
+        Box::new(PhaseGate::tdg(0)),
+        Box::new(CPhase::new(1, 3, 0.7)),
+        Box::new(PauliRotation::new(
+            PauliString::new(vec![(0, Pauli::X), (2, Pauli::Y)]),
+            0.3,
+        )),
+        Box::new(MatrixGate::new(
+            vec![2],
+            vec![vec![c, d], vec![d, c]],
+            String::from("m"),
+        )),
+        Box::new(PhaseOracle::new(
+            vec![0, 3],
+            |x| 0.2 * x as f64,
+            String::from("ph"),
+        )),
+        Box::new(PermutationOracle::bijective(
+            vec![0, 2, 3],
+            |x| (3 * x + 1) % 8,
+            String::from("affine"),
+        )),
+        Box::new(PermutationOracle::xor(
+            vec![0, 3],
+            vec![2],
+            |x| x & 1,
+            String::from("f"),
+        )),
+        // no fast path: repeated application
+        Box::new(U::new(
+            vec![Box::new(U3::new(0, 0.3, 0.2, 0.1)), Box::new(CX::new(0, 2))],
+            String::from("u"),
+        )),
+        // commuting gates: power of every gate
+        Box::new(U::new(
+            vec![
+                Box::new(R::new(3, 0.4)),
+                Box::new(CPhase::new(1, 3, 0.3)),
+                Box::new(H::new(0)),
+            ],
+            String::from("commuting"),
+        )),
+        Box::new(SX::new(2)),
+        Box::new(ISWAP::new(3, 0)),
+        Box::new(U3::new(1, 0.3, 0.2, 0.1)),
+        Box::new(CU::new(
+            1,
+            vec![Box::new(R::new(3, 0.4)), Box::new(PhaseGate::t(3))],
+            String::from("c-phase"),
+        )),
+    ];
+    for op in ops {
+        for k in [0, 1, 2, 3, 5] {
+            let mut expected = q_in.clone();
+            for _ in 0..k {
+                expected = op.apply(expected);
+            }
+            let mut pow = Pow::new(op.clone(), k);
+            let q_out = pow.apply(q_in.clone());
+            isequal_qubits(&q_out, &expected);
+
+            // lowered inside a CU on the otherwise unused qubit 4
+            let cu = CU::new(4, vec![Box::new(pow.clone())], String::from("c-pow"));
+            let mut sparse = SparseQubits::from_qubits(&q_in);
+            sparse.apply(&cu).unwrap();
+            isequal_qubits(&sparse.to_qubits(), &cu.apply(q_in.clone()));
+
+            isequal_qubits(&pow.dagger().apply(q_out.clone()), &q_in);
+            pow.inverse();
+            isequal_qubits(&pow.apply(q_out), &q_in);
+        }
+    }
+
+    // U has a native power only when its gates commute
+    let commuting = U::new(
+        vec![
+            Box::new(R::new(3, 0.4)),
+            Box::new(CPhase::new(1, 3, 0.3)),
+            Box::new(H::new(0)),
+        ],
+        String::from("commuting"),
+    );
+    assert_eq!(commuting.power(3).unwrap().describe().label, "commuting^3");
+    let overlapping = U::new(
+        vec![Box::new(H::new(0)), Box::new(PhaseGate::s(0))],
+        String::from("overlapping"),
+    );
+    assert!(overlapping.power(3).is_none());
+
+    // and so does a CU, e.g. the controlled U^(2^j) of phase estimation
+    let cu = CU::new(1, vec![Box::new(R::new(3, 0.4))], String::from("c-r"));
+    assert_eq!(cu.power(8).unwrap().describe().label, "c-r^8");
+    let cu = CU::new(1, vec![Box::new(overlapping)], String::from("c-o"));
+    assert!(cu.power(8).is_none());
+    for op in [
+        Box::new(SX::new(0)) as Box<dyn Operator>,
+        Box::new(ISWAP::new(0, 1)),
+        Box::new(U3::new(0, 0.3, 0.2, 0.1)),
+    ] {
+        assert!(op.power(6).is_some());
+    }
+
+    // an overflowing exponent has no native power
+    let pow = Pow::new(Box::new(PhaseGate::s(0)), usize::MAX);
+    assert!(pow.power(2).is_none());
+    assert!(pow.power(1).is_some());
+}
+
+fn isequal_matrix(a: &[Vec<Comp>], b: &[Vec<Comp>]) {
+    assert_eq!(a.len(), b.len());
+    for (i, (ra, rb)) in a.iter().zip(b.iter()).enumerate() {
+        for (j, (x, y)) in ra.iter().zip(rb.iter()).enumerate() {
+            assert!(isequal_comp(x, y), "m[{}][{}]: {} != {}", i, j, x, y);
+        }
+    }
+}
+
+#[test]
+fn test_unitary() {
+    use super::circuits::qft;
+    use super::gates::{CPhase, PhaseGate, CX, H, ISWAP, RX, SWAP, SX, Y};
+    use super::unitary::{format_matrix, is_unitary, unitary};
+
+    let (o, l, i) = (Comp::zero(), Comp::new(1.0, 0.0), Comp::new(0.0, 1.0));
+    let s = 1.0 / 2.0_f64.sqrt();
+    let e4 = Comp::new(s, s);
+
+    isequal_matrix(
+        &unitary(&H::new(0), 1),
+        &[
+            vec![Comp::new(s, 0.0), Comp::new(s, 0.0)],
+            vec![Comp::new(s, 0.0), Comp::new(-s, 0.0)],
+        ],
+    );
+    // gates::Y is -Y
+    isequal_matrix(&unitary(&Y::new(0), 1), &[vec![o, i], vec![i * -1.0, o]]);
+    isequal_matrix(&unitary(&PhaseGate::s(0), 1), &[vec![l, o], vec![o, i]]);
+    isequal_matrix(&unitary(&PhaseGate::t(0), 1), &[vec![l, o], vec![o, e4]]);
+    let (p, m) = (Comp::new(0.5, 0.5), Comp::new(0.5, -0.5));
+    isequal_matrix(&unitary(&SX::new(0), 1), &[vec![p, m], vec![m, p]]);
+    let theta: f64 = 0.7;
+    let (c, si) = (
+        Comp::new((theta / 2.0).cos(), 0.0),
+        Comp::new(0.0, -(theta / 2.0).sin()),
+    );
+    isequal_matrix(&unitary(&RX::new(0, theta), 1), &[vec![c, si], vec![si, c]]);
+
+    // control 0, target 1: |01⟩ <-> |11⟩
+    isequal_matrix(
+        &unitary(&CX::new(0, 1), 2),
+        &[
+            vec![l, o, o, o],
+            vec![o, o, o, l],
+            vec![o, o, l, o],
+            vec![o, l, o, o],
+        ],
+    );
+    isequal_matrix(
+        &unitary(&SWAP::new(0, 1), 2),
+        &[
+            vec![l, o, o, o],
+            vec![o, o, l, o],
+            vec![o, l, o, o],
+            vec![o, o, o, l],
+        ],
+    );
+    isequal_matrix(
+        &unitary(&ISWAP::new(0, 1), 2),
+        &[
+            vec![l, o, o, o],
+            vec![o, o, i, o],
+            vec![o, i, o, o],
+            vec![o, o, o, l],
+        ],
+    );
+    isequal_matrix(
+        &unitary(&CPhase::new(0, 1, PI / 4.0), 2),
+        &[
+            vec![l, o, o, o],
+            vec![o, l, o, o],
+            vec![o, o, l, o],
+            vec![o, o, o, e4],
+        ],
+    );
+
+    // the operator acts on qubit 1 of a wider register
+    let wide = unitary(&H::new(1), 3);
+    assert_eq!(wide.len(), 8);
+    assert!(isequal_comp(&wide[0b010][0b000], &Comp::new(s, 0.0)));
+    assert!(isequal_comp(&wide[0b001][0b000], &o));
+
+    // QFT on 3 qubits: m[k][j] = ω^(jk)/√8 with ω = exp(2πi/8)
+    let f = unitary(&qft(&[0, 1, 2]), 3);
+    assert!(is_unitary(&f));
+    let expected: Vec<Vec<Comp>> = (0..8)
+        .map(|k| {
+            (0..8)
+                .map(|j| {
+                    let angle = 2.0 * PI * ((j * k) % 8) as f64 / 8.0;
+                    Comp::new(angle.cos(), angle.sin()) * (1.0 / 8.0_f64.sqrt())
+                })
+                .collect()
+        })
+        .collect();
+    isequal_matrix(&f, &expected);
+
+    assert!(!is_unitary(&[vec![l, l], vec![o, l]]));
+    assert!(!is_unitary(&[vec![l, o]]));
+    assert_eq!(
+        format_matrix(&[vec![l, o], vec![o, i]]),
+        "+1.000 +0.000i  +0.000 +0.000i\n+0.000 +0.000i  +0.000 +1.000i\n"
+    );
+}
+
+#[test]
+fn test_equivalence() {
+    use super::circuits::{full_adder_nbits, inv_qft, qft, substract_nbits};
+    use super::equivalence::{
+        equivalent, equivalent_permutation, equivalent_random, equivalent_unitary,
+    };
+    use super::gates::{CX, H, R, RZ, SWAP, U, X, Y, Z};
+
+    let cxs = U::new(
+        vec![
+            Box::new(CX::new(0, 2)),
+            Box::new(CX::new(2, 0)),
+            Box::new(CX::new(0, 2)),
+        ],
+        String::from("cxs"),
+    );
+    assert!(equivalent_unitary(&SWAP::new(0, 2), &cxs, 3));
+    assert!(!equivalent_unitary(&CX::new(0, 2), &CX::new(2, 0), 3));
+    // RZ(θ) = exp(-iθ/2)R(θ)
+    assert!(equivalent_unitary(&RZ::new(1, 0.7), &R::new(1, 0.7), 2));
+    // a relative phase is not a global phase
+    let identity = U::new(vec![], String::from("id"));
+    assert!(!equivalent_unitary(&Z::new(0), &identity, 1));
+    assert!(!equivalent_unitary(&X::new(0), &Y::new(0), 1));
+
+    // 10 qubits: compared on random states
+    let x: Vec<usize> = (0..10).collect();
+    let round_trip = U::new(
+        vec![Box::new(qft(&x)), Box::new(inv_qft(&x))],
+        String::from("round_trip"),
+    );
+    assert!(equivalent_random(&round_trip, &identity, 10, 3, Some(7)));
+    assert!(!equivalent_random(&qft(&x), &inv_qft(&x), 10, 3, Some(7)));
+    assert!(equivalent(&round_trip, &identity, 10));
+    assert!(!equivalent(&H::new(3), &identity, 10));
+
+    // a small angle error is caught by both checks at the same tolerance
+    let (r, r_off) = (R::new(0, 0.7), R::new(0, 0.7 + 1e-6));
+    assert!(!equivalent_unitary(&r, &r_off, 2));
+    assert!(!equivalent_random(&r, &r_off, 10, 3, Some(7)));
+    assert!(!equivalent(&r, &r_off, 10));
+
+    // 9-qubit adder and the adjoint of the subtractor
+    let (a, b, c) = (vec![0, 1, 2], vec![3, 4, 5], vec![6, 7, 8]);
+    let adder = full_adder_nbits(&a, &b, &c);
+    let sub_adj = substract_nbits(&a, &b, &c).adjoint();
+    assert!(equivalent(&adder, &sub_adj, 9));
+    let inputs: Vec<u128> = (0..1 << 9).collect();
+    assert_eq!(
+        equivalent_permutation(&adder, &sub_adj, 9, &inputs),
+        Ok(true)
+    );
+    assert_eq!(
+        equivalent_permutation(&adder, &substract_nbits(&a, &b, &c), 9, &inputs),
+        Ok(false)
+    );
+    // phases are ignored
+    assert_eq!(
+        equivalent_permutation(&X::new(0), &Y::new(0), 1, &[0, 1]),
+        Ok(true)
+    );
+    assert!(equivalent_permutation(&adder, &H::new(0), 9, &inputs).is_err());
+}
+
+#[test]
+fn test_remap() {
+    use super::circuits::full_adder_nbits;
+    use super::equivalence::{equivalent, equivalent_unitary};
+    use super::gates::{
+        CPhase, MatrixGate, PauliRotation, PermutationOracle, PhaseGate, PhaseOracle, Pow, CCX,
+        CNX, CSWAP, CU, CX, CZ, H, ISWAP, MCZ, RX, RY, SWAP, SX, U, U3, X, Y,
+    };
+    use super::observable::{Pauli, PauliString};
+
+    // every kind of gate, on the qubits q[0..4]
+    fn circ(q: &[usize]) -> U {
+        let (o, l) = (Comp::zero(), Comp::new(1.0, 0.0));
+        let mut s = PhaseGate::s(q[1]);
+        s.inverse();
+        let inner = U::new(
+            vec![Box::new(RX::new(q[2], 0.4)), Box::new(CX::new(q[0], q[2]))],
+            String::from("inner"),
+        );
+        let gates: Vec<Box<dyn Operator>> = vec![
+            Box::new(H::new(q[0])),
+            Box::new(RY::new(q[1], 0.3)),
+            Box::new(U3::new(q[2], 0.2, 0.5, 0.7)),
+            Box::new(SX::new(q[3])),
+            Box::new(s),
+            Box::new(CX::new_polarized(q[0], false, q[2])),
+            Box::new(CZ::new(q[1], q[3])),
+            Box::new(CPhase::new(q[2], q[0], 0.9)),
+            Box::new(CCX::new(q[3], q[1], q[0])),
+            Box::new(CNX::new_polarized(
+                vec![q[0], q[1]],
+                vec![true, false],
+                q[3],
+            )),
+            Box::new(MCZ::new(vec![q[0], q[2], q[3]])),
+            Box::new(SWAP::new(q[0], q[3])),
+            Box::new(ISWAP::new(q[1], q[2])),
+            Box::new(CSWAP::new(q[2], q[0], q[1])),
+            Box::new(MatrixGate::new(
+                vec![q[3], q[1]],
+                vec![
+                    vec![o, l, o, o],
+                    vec![l, o, o, o],
+                    vec![o, o, o, l],
+                    vec![o, o, l, o],
+                ],
+                String::from("m"),
+            )),
+            Box::new(PauliRotation::new(
+                PauliString::new(vec![(q[0], Pauli::X), (q[2], Pauli::Z)]),
+                0.6,
+            )),
+            Box::new(PhaseOracle::new(
+                vec![q[1], q[3]],
+                |x| 0.3 * x as f64,
+                String::from("ph"),
+            )),
+            Box::new(PermutationOracle::bijective(
+                vec![q[2], q[0], q[3]],
+                |x| (x + 3) % 8,
+                String::from("+3"),
+            )),
+            Box::new(PermutationOracle::xor(
+                vec![q[0], q[1]],
+                vec![q[2]],
+                |x| x & 1,
+                String::from("f"),
+            )),
+            Box::new(Pow::new(Box::new(PhaseGate::t(q[2])), 3)),
+            Box::new(CU::new_multi_polarized(
+                vec![q[1], q[3]],
+                vec![true, false],
+                vec![Box::new(inner), Box::new(Y::new(q[0]))],
+                String::from("cu"),
+            )),
+            Box::new(X::new(q[1])),
+        ];
+        U::new(gates, String::from("circ"))
+    }
+
+    let q_in = [0, 1, 2, 3];
+    let perm = [2, 0, 3, 1];
+    let remapped = circ(&q_in).remap(&|q| perm[q]).unwrap();
+    assert!(equivalent_unitary(remapped.as_ref(), &circ(&perm), 4));
+    assert!(!equivalent_unitary(remapped.as_ref(), &circ(&q_in), 4));
+    assert_eq!(
+        CX::new(0, 2)
+            .remap(&|q| perm[q])
+            .unwrap()
+            .describe()
+            .qubits(),
+        vec![2, 3]
+    );
+
+    // offset onto qubits 2..6
+    assert!(equivalent_unitary(
+        &circ(&q_in).offset(2).unwrap(),
+        &circ(&[2, 3, 4, 5]),
+        6
+    ));
+
+    // an adder moved onto interleaved registers
+    let adder = full_adder_nbits(&[0, 1, 2], &[3, 4, 5], &[6, 7, 8]);
+    let interleave = |q: usize| (q % 3) * 3 + q / 3;
+    let moved = full_adder_nbits(&[0, 3, 6], &[1, 4, 7], &[2, 5, 8]);
+    assert!(equivalent(
+        adder.remap(&interleave).unwrap().as_ref(),
+        &moved,
+        9
+    ));
+}
+
+#[test]
+#[should_panic(expected = "same index")]
+fn test_remap_rejects_collision() {
+    use super::gates::CX;
+    CX::new(0, 1).remap(&|_| 0);
+}
+
+#[test]
+#[should_panic(expected = "control bit 0 and a qubit of ch to the same index")]
+fn test_remap_rejects_control_collision() {
+    use super::gates::{CU, H};
+    let cu = CU::new(0, vec![Box::new(H::new(5))], String::from("ch"));
+    cu.remap(&|q| if q == 0 { 5 } else { q });
+}
+
+/**
+X(b)・CX(a, b), defined outside the gates module with the default `describe` and `remap`.
+ */
+#[derive(Clone)]
+struct ExternalGate {
+    a: usize,
+    b: usize,
+}
+
+impl Applicable for ExternalGate {
+    fn name(&self) -> String {
+        format!("External({}, {})", self.a, self.b)
+    }
+
+    fn apply_iter(&self, qubits: Qubits, iter: &super::core::BitSlideIndex) -> Qubits {
+        use super::gates::{CX, X};
+        let qubits = CX::new(self.a, self.b).apply_iter(qubits, iter);
+        X::new(self.b).apply_iter(qubits, iter)
+    }
+}
+
+impl Inversible for ExternalGate {
+    // X on the target commutes with CX
+    fn dagger(&self) -> Box<dyn Operator> {
+        self.box_clone()
+    }
+}
+
+impl Operator for ExternalGate {}
+
+#[test]
+fn test_remap_default() {
+    use super::gates::{Pow, CU, H, U};
+
+    // qubits 5 and 6 moved into a 3-qubit register: no result, and no panic
+    let gate = ExternalGate { a: 5, b: 6 };
+    assert!(gate.remap(&|q| q - 5).is_none());
+
+    // nor through the gates that contain it
+    let u = U::new(
+        vec![Box::new(H::new(5)), Box::new(gate.clone())],
+        String::from("u"),
+    );
+    assert!(u.remap(&|q| q - 5).is_none());
+    assert!(u.offset(1).is_none());
+    let cu = CU::new(0, vec![Box::new(gate.clone())], String::from("cu"));
+    assert!(cu.remap(&|q| q + 1).is_none());
+    assert!(Pow::new(Box::new(gate), 3).remap(&|q| q + 1).is_none());
 }
@@ -0,0 +1,85 @@
+/*!
+ Matrix form of operators.
+
+ The simulator never builds matrices to apply gates, but for a few qubits the full unitary is a
+ convenient way to check an operator against its textbook definition. [`unitary`] builds it column by
+ column: column j is the operator applied to the basis state |j⟩.
+
+# Example
+```
+use Qit::gates::{CX, H};
+use Qit::unitary::{format_matrix, is_unitary, unitary};
+
+let m = unitary(&CX::new(0, 1), 2);
+// |01⟩ -> |11⟩
+assert!((m[0b11][0b01].0 - 1.0).abs() < 1e-9);
+assert!(is_unitary(&m));
+
+let h = unitary(&H::new(0), 1);
+assert_eq!(
+    format_matrix(&h),
+    "+0.707 +0.000i  +0.707 +0.000i\n+0.707 +0.000i  -0.707 +0.000i\n"
+);
+```
+*/
+
+use super::core::{Comp, Operator, Qubits};
+
+const ZERO: Comp = Comp(0.0, 0.0);
+const ONE: Comp = Comp(1.0, 0.0);
+
+/**
+Matrix of `op` on `n_qubits` qubits, indexed as `m[row][column]`. Bit i of an index is qubit i.
+
+Every index used by `op` must be smaller than `n_qubits`. The cost is 2^n applications of `op`, so
+this is meant for small widths.
+ */
+pub fn unitary(op: &dyn Operator, n_qubits: usize) -> Vec<Vec<Comp>> {
+    let dim = 1 << n_qubits;
+    let columns: Vec<Qubits> = (0..dim)
+        .map(|column| op.apply(Qubits::from_num(n_qubits, column)))
+        .collect();
+    (0..dim)
+        .map(|row| columns.iter().map(|c| c.bits[row]).collect())
+        .collect()
+}
+
+/**
+Check M†M = I within 1e-9. Non-square matrices are not unitary.
+ */
+pub fn is_unitary(m: &[Vec<Comp>]) -> bool {
+    let dim = m.len();
+    if m.iter().any(|row| row.len() != dim) {
+        return false;
+    }
+    for i in 0..dim {
+        for j in 0..dim {
+            let mut sum = Comp::zero();
+            for row in m.iter() {
+                sum = sum + Comp(row[i].0, -row[i].1) * row[j];
+            }
+            let expected = if i == j { ONE } else { ZERO };
+            if (sum - expected).abs_square() > 1e-18 {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/**
+One line per row, entries written like [`Qubits::print_cmps`] and separated by two spaces.
+ */
+pub fn format_matrix(m: &[Vec<Comp>]) -> String {
+    let mut out = String::new();
+    for row in m.iter() {
+        let entries: Vec<String> = row.iter().map(|c| format!("{}", c)).collect();
+        out.push_str(&entries.join("  "));
+        out.push('\n');
+    }
+    out
+}
+
+pub fn print_matrix(m: &[Vec<Comp>]) {
+    print!("{}", format_matrix(m));
+}